@@ -0,0 +1,129 @@
+use std::io::{Read, Seek, Write};
+
+use super::{compress_entry, create, open};
+use crate::error::Error;
+
+/// A modification applied to an archive by [`apply_changes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryChange {
+    /// Replaces the contents of an existing entry. The entry stays compressed
+    /// if it was compressed before.
+    Replace { identifier: u32, data: Vec<u8> },
+    /// Appends a new entry, compressed with the algorithm of the archive if
+    /// requested and supported.
+    Add {
+        identifier: u32,
+        data: Vec<u8>,
+        compressed: bool,
+    },
+    /// Removes an existing entry.
+    Remove { identifier: u32 },
+}
+
+impl EntryChange {
+    /// Returns the identifier of the entry the change applies to.
+    pub fn identifier(&self) -> u32 {
+        match self {
+            Self::Replace { identifier, .. }
+            | Self::Add { identifier, .. }
+            | Self::Remove { identifier } => *identifier,
+        }
+    }
+}
+
+/// Copies the archive from `reader` into `writer` while applying `changes`.
+///
+/// Entries which are not changed are copied byte for byte, without
+/// decompressing and compressing them again. Added entries are appended in
+/// the given order. Replacing or removing an entry which does not exist and
+/// adding an entry whose identifier is already taken are errors.
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::{BufReader, BufWriter};
+/// use vt7packer::archive::{self, EntryChange};
+///
+/// let source = BufReader::new(File::open("graphics_2x.vt7a")?);
+/// let mut target = BufWriter::new(File::create("graphics_2x.modded.vt7a")?);
+/// let changes = [EntryChange::Replace {
+///     identifier: 0x0000a123,
+///     data: std::fs::read("flag.webp")?,
+/// }];
+/// archive::apply_changes(source, &mut target, &changes)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn apply_changes<R: Read + Seek, W: Write + Seek>(
+    reader: R,
+    writer: W,
+    changes: &[EntryChange],
+) -> Result<(), Error> {
+    let mut archive = open(reader)?;
+    let entries = archive.entries().to_vec();
+
+    let mut added: Vec<u32> = vec![];
+    for change in changes {
+        let identifier = change.identifier();
+        let exists = entries.iter().any(|e| e.identifier == identifier);
+        match change {
+            EntryChange::Replace { .. } | EntryChange::Remove { .. } if !exists => {
+                return Err(Error::parsing(format!(
+                    "Cannot change {:08x}, the archive has no such entry",
+                    identifier
+                )));
+            }
+            EntryChange::Add { .. } if exists || added.contains(&identifier) => {
+                return Err(Error::parsing(format!(
+                    "Cannot add {:08x}, the identifier collides with an existing entry",
+                    identifier
+                )));
+            }
+            EntryChange::Add { .. } => added.push(identifier),
+            _ => {}
+        }
+    }
+
+    let kept_entries: Vec<_> = entries
+        .iter()
+        .filter(|entry| {
+            !changes.iter().any(|c| {
+                matches!(c, EntryChange::Remove { identifier } if *identifier == entry.identifier)
+            })
+        })
+        .collect();
+
+    let kind = archive.kind();
+    let mut writer = create(writer, kind, (kept_entries.len() + added.len()) as u32)?;
+    for entry in kept_entries {
+        let replacement = changes.iter().rev().find_map(|c| match c {
+            EntryChange::Replace { identifier, data } if *identifier == entry.identifier => {
+                Some(data)
+            }
+            _ => None,
+        });
+        match replacement {
+            Some(data) => {
+                let (new_entry, stored_data) =
+                    compress_entry(entry.identifier, data, entry.compression)
+                        .map_err(|e| e.with_resource(entry.identifier))?;
+                writer.write_raw_entry(&new_entry, &stored_data)?;
+            }
+            None => {
+                let stored_data = archive.read_raw(entry)?;
+                writer.write_raw_entry(entry, &stored_data)?;
+            }
+        }
+    }
+    for change in changes {
+        if let EntryChange::Add {
+            identifier,
+            data,
+            compressed,
+        } = change
+        {
+            writer
+                .write_entry(*identifier, data, *compressed)
+                .map_err(|e| e.with_resource(*identifier))?;
+        }
+    }
+    writer.finish()
+}
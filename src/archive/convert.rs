@@ -0,0 +1,86 @@
+use std::io::{Cursor, Read, Seek, Write};
+
+use rayon::prelude::*;
+
+use super::{
+    compress_entry, is_archive, ArchiveEntry, ArchiveReader, ArchiveWriter, Vt7aArchive,
+    Vt7aVersion, Vt7aWriter,
+};
+use crate::compression::decompress;
+use crate::error::Error;
+
+/// Copies the VT7A archive from `reader` into `writer` as an archive of
+/// `version`, e.g. to move a Reforged archive (version 3, zstd) to the engine
+/// of the original games (version 2, zlib).
+///
+/// Compressed entries are recompressed with the algorithm of the new version,
+/// stored entries stay stored. VT7A archives inside the archive are converted
+/// as well. Entries which already use the right algorithm are copied as they
+/// are, so converting to the same version only converts nested archives.
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::{BufReader, BufWriter};
+/// use vt7packer::archive::{self, Vt7aVersion};
+///
+/// let source = BufReader::new(File::open("text.vt7a")?);
+/// let mut target = BufWriter::new(File::create("text.v2.vt7a")?);
+/// archive::convert_version(source, &mut target, Vt7aVersion::Two)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn convert_version<R: Read + Seek, W: Write + Seek>(
+    reader: R,
+    writer: W,
+    version: Vt7aVersion,
+) -> Result<(), Error> {
+    let mut archive = Vt7aArchive::new(reader)?;
+    let entries = archive.entries().to_vec();
+    let mut writer = Vt7aWriter::new(writer, version, archive.timestamp(), entries.len() as u32)?;
+
+    // Only keep as many entries in memory as can be worked on in parallel
+    for chunk in entries.chunks(rayon::current_num_threads()) {
+        let stored_entries = chunk
+            .iter()
+            .map(|entry| Ok((*entry, archive.read_raw(entry)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let converted_entries = stored_entries
+            .into_par_iter()
+            .map(|(entry, stored_data)| {
+                convert_entry(entry, stored_data, version)
+                    .map_err(|e| e.with_resource(entry.identifier))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        for (entry, stored_data) in converted_entries {
+            writer.write_raw_entry(&entry, &stored_data)?;
+        }
+    }
+    writer.finish()
+}
+
+/// Returns the entry and its stored data as they are written into an archive
+/// of `version`.
+fn convert_entry(
+    entry: ArchiveEntry,
+    stored_data: Vec<u8>,
+    version: Vt7aVersion,
+) -> Result<(ArchiveEntry, Vec<u8>), Error> {
+    let data = decompress(&stored_data, entry.compression)?;
+    let nested = is_archive(&data) && Vt7aArchive::new(Cursor::new(&data)).is_ok();
+    if !nested && (!entry.is_compressed() || entry.compression == version.compression()) {
+        return Ok((entry, stored_data));
+    }
+
+    let data = match nested {
+        true => {
+            let mut converted = Cursor::new(vec![]);
+            convert_version(Cursor::new(&data), &mut converted, version)?;
+            converted.into_inner()
+        }
+        false => data,
+    };
+    let compression = match entry.is_compressed() {
+        true => version.compression(),
+        false => entry.compression,
+    };
+    compress_entry(entry.identifier, &data, compression)
+}
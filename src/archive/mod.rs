@@ -0,0 +1,397 @@
+//! Random access to the entries of VT7A and AUFS archives.
+//!
+//! Opening an archive only parses its header and directory. The contents of
+//! an entry are read (and decompressed) when it is requested, so single
+//! entries can be pulled out of large archives without loading them.
+//!
+//! The writers work the other way around: they stream one entry at a time
+//! into the output and fill in the directory at the end.
+//!
+//! ```no_run
+//! use std::{fs::File, io::BufReader};
+//! use vt7packer::archive::{self, ArchiveReader};
+//!
+//! let mut archive = archive::open(BufReader::new(File::open("graphics_2x.vt7a")?))?;
+//! let entry = *archive.find(0x0000a123).unwrap();
+//! let data = archive.read(&entry)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+mod changes;
+mod convert;
+mod osa_archive;
+mod patch;
+mod vt7a_archive;
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::compression::{compress, decompress, default_level, CompressionFormat};
+use crate::error::{Error, ErrorKind};
+
+pub use changes::{apply_changes, EntryChange};
+pub use convert::convert_version;
+pub use osa_archive::{OsaArchive, OsaWriter};
+pub use patch::{Patch, PatchData, PatchEntry};
+pub use vt7a_archive::{format_timestamp, Vt7aArchive, Vt7aVersion, Vt7aWriter};
+
+/// A single entry from the directory of an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// File identifier
+    pub identifier: u32,
+    /// Offset of the data from the beginning of the archive
+    pub offset: u32,
+    /// Size of the data after decompression
+    pub size: u32,
+    /// Size of the data inside the archive, 0 if it is not compressed
+    pub compressed_size: u32,
+    /// Algorithm the data is compressed with
+    pub compression: CompressionFormat,
+}
+
+impl ArchiveEntry {
+    /// Returns whether the entry is stored compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.compressed_size != 0
+    }
+
+    /// Returns the amount of bytes the entry occupies inside the archive.
+    pub fn stored_size(&self) -> u32 {
+        if self.is_compressed() {
+            self.compressed_size
+        } else {
+            self.size
+        }
+    }
+}
+
+/// Type of an archive together with the header fields needed to write an
+/// archive of the same type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    /// VT7A archive (.vt7a)
+    Vt7a {
+        /// Version, which decides the compression algorithm
+        version: Vt7aVersion,
+        /// Third field of the header, most likely the time of creation
+        timestamp: u32,
+    },
+    /// AUFS archive (.osa)
+    Osa,
+}
+
+impl ArchiveKind {
+    /// Returns the size of header and directory of an archive with
+    /// `number_of_entries` entries, where the data of the entries begins.
+    pub fn data_offset(&self, number_of_entries: usize) -> u64 {
+        let (header_size, entry_size) = match self {
+            Self::Vt7a { .. } => (16, 16),
+            Self::Osa => (8, 12),
+        };
+        header_size + entry_size * number_of_entries as u64
+    }
+}
+
+/// Read access to the entries of an archive.
+pub trait ArchiveReader {
+    /// Returns the type and header fields of the archive.
+    fn kind(&self) -> ArchiveKind;
+
+    /// Returns the directory of the archive in stored order.
+    fn entries(&self) -> &[ArchiveEntry];
+
+    /// Reads the bytes of an entry as they are stored in the archive.
+    fn read_raw(&mut self, entry: &ArchiveEntry) -> Result<Vec<u8>, Error>;
+
+    /// Reads and decompresses the bytes of an entry.
+    fn read(&mut self, entry: &ArchiveEntry) -> Result<Vec<u8>, Error> {
+        decompress(&self.read_raw(entry)?, entry.compression)
+    }
+
+    /// Returns the first entry with the given identifier.
+    fn find(&self, identifier: u32) -> Option<&ArchiveEntry> {
+        self.entries().iter().find(|e| e.identifier == identifier)
+    }
+}
+
+/// Write access to a new archive.
+///
+/// The amount of entries is fixed when the writer is created, as the
+/// directory is reserved in front of the data. Every entry has to be written
+/// before [`ArchiveWriter::finish`] is called.
+pub trait ArchiveWriter {
+    /// Writes the next entry, compressing it if requested and supported.
+    fn write_entry(
+        &mut self,
+        identifier: u32,
+        data: &[u8],
+        compressed: bool,
+    ) -> Result<ArchiveEntry, Error>;
+
+    /// Writes the next entry from bytes exactly as they are stored in an
+    /// archive, e.g. taken from [`ArchiveReader::read_raw`]. Only the offset of
+    /// `entry` is ignored.
+    fn write_raw_entry(&mut self, entry: &ArchiveEntry, data: &[u8])
+        -> Result<ArchiveEntry, Error>;
+
+    /// Like [`ArchiveWriter::write_raw_entry`], but puts the entry at position
+    /// `index` of the directory. This allows storing the data in a different
+    /// order than the directory lists it. Entries written without an index
+    /// take the first free position.
+    fn write_raw_entry_at(
+        &mut self,
+        index: usize,
+        entry: &ArchiveEntry,
+        data: &[u8],
+    ) -> Result<ArchiveEntry, Error>;
+
+    /// Writes bytes which belong to no entry, e.g. padding between entries.
+    fn write_padding(&mut self, data: &[u8]) -> Result<(), Error>;
+
+    /// Writes the directory after all entries have been written.
+    fn finish(&mut self) -> Result<(), Error>;
+}
+
+/// Opens a VT7A or AUFS archive, depending on its magic bytes.
+pub fn open<'a, R: Read + Seek + 'a>(mut reader: R) -> Result<Box<dyn ArchiveReader + 'a>, Error> {
+    let mut magic: [u8; 4] = [0; 4];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut magic)?;
+
+    match &magic {
+        b"VT7A" => Ok(Box::new(Vt7aArchive::new(reader)?)),
+        b"AUFS" => Ok(Box::new(OsaArchive::new(reader)?)),
+        _ => Err(Error::new(ErrorKind::BadMagic {
+            expected: "VT7A or AUFS".to_string(),
+            found: magic.to_vec(),
+        })
+        .at_offset(0)),
+    }
+}
+
+/// Creates a writer for a new archive of the given kind with room for
+/// `number_of_entries` entries.
+pub fn create<'a, W: Write + Seek + 'a>(
+    writer: W,
+    kind: ArchiveKind,
+    number_of_entries: u32,
+) -> Result<Box<dyn ArchiveWriter + 'a>, Error> {
+    match kind {
+        ArchiveKind::Vt7a { version, timestamp } => Ok(Box::new(Vt7aWriter::new(
+            writer,
+            version,
+            timestamp,
+            number_of_entries,
+        )?)),
+        ArchiveKind::Osa => Ok(Box::new(OsaWriter::new(writer, number_of_entries)?)),
+    }
+}
+
+/// Returns whether the data starts with the magic bytes of a known archive.
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(b"VT7A") || data.starts_with(b"AUFS")
+}
+
+fn read_directory<R: Read + Seek>(
+    reader: &mut R,
+    header_size: u64,
+    entry_size: u64,
+    number_of_entries: u32,
+) -> Result<Vec<u8>, Error> {
+    let directory_size = entry_size * number_of_entries as u64;
+    let archive_size = reader.seek(SeekFrom::End(0))?;
+    if header_size + directory_size > archive_size {
+        return Err(Error::new(ErrorKind::Truncated(format!(
+            "Archive directory with {} entries does not fit into {} bytes",
+            number_of_entries, archive_size
+        )))
+        .at_offset(header_size));
+    }
+
+    let mut directory = vec![0; directory_size as usize];
+    reader.seek(SeekFrom::Start(header_size))?;
+    reader.read_exact(&mut directory)?;
+    Ok(directory)
+}
+
+fn read_entry_data<R: Read + Seek>(reader: &mut R, entry: &ArchiveEntry) -> Result<Vec<u8>, Error> {
+    let archive_size = reader.seek(SeekFrom::End(0))?;
+    if entry.offset as u64 + entry.stored_size() as u64 > archive_size {
+        return Err(Error::new(ErrorKind::OffsetOutOfRange {
+            offset: entry.offset as u64,
+            length: entry.stored_size() as u64,
+            size: archive_size,
+        })
+        .with_resource(entry.identifier));
+    }
+
+    let mut data = vec![0; entry.stored_size() as usize];
+    reader.seek(SeekFrom::Start(entry.offset as u64))?;
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Compresses the data of an entry the way [`ArchiveWriter::write_entry`]
+/// would and returns it together with its directory entry (with offset 0).
+///
+/// This allows compressing several entries in parallel before passing them to
+/// [`ArchiveWriter::write_raw_entry`] in order.
+pub fn compress_entry(
+    identifier: u32,
+    data: &[u8],
+    compression: CompressionFormat,
+) -> Result<(ArchiveEntry, Vec<u8>), Error> {
+    compress_entry_with_level(identifier, data, compression, default_level(compression))
+}
+
+/// Like [`compress_entry`], but with the given compression level.
+pub fn compress_entry_with_level(
+    identifier: u32,
+    data: &[u8],
+    compression: CompressionFormat,
+    level: i32,
+) -> Result<(ArchiveEntry, Vec<u8>), Error> {
+    let stored_data = compress(data, compression, level)?;
+    let entry = ArchiveEntry {
+        identifier,
+        offset: 0,
+        size: size_field(identifier, data.len())?,
+        compressed_size: match compression {
+            CompressionFormat::None => 0,
+            _ => size_field(identifier, stored_data.len())?,
+        },
+        compression,
+    };
+    Ok((entry, stored_data))
+}
+
+/// Converts the size of an entry into the 32 bit field of a directory entry.
+pub(crate) fn size_field(identifier: u32, size: usize) -> Result<u32, Error> {
+    u32::try_from(size).map_err(|_| {
+        Error::parsing(format!(
+            "Entry {:08x} is too large with {} bytes",
+            identifier, size
+        ))
+    })
+}
+
+/// Shared bookkeeping of the archive writers: the position of the next entry
+/// and the directory collected so far.
+#[derive(Debug)]
+struct WriterState {
+    directory_offset: u64,
+    position: u64,
+    entries: Vec<Option<ArchiveEntry>>,
+    /// First position of the directory which is still free
+    next_free: usize,
+}
+
+impl WriterState {
+    fn new<W: Write + Seek>(
+        writer: &mut W,
+        header: &[u8],
+        entry_size: u64,
+        number_of_entries: u32,
+    ) -> Result<Self, Error> {
+        let directory_offset = header.len() as u64;
+        let directory_size = entry_size * number_of_entries as u64;
+
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(header)?;
+        // Placeholder, the directory is written in finish()
+        std::io::copy(&mut std::io::repeat(0).take(directory_size), writer)?;
+
+        Ok(Self {
+            directory_offset,
+            position: directory_offset + directory_size,
+            entries: vec![None; number_of_entries as usize],
+            next_free: 0,
+        })
+    }
+
+    /// Returns the first position of the directory which is still free.
+    fn next_index(&self) -> usize {
+        self.next_free
+    }
+
+    fn write_data<W: Write>(
+        &mut self,
+        writer: &mut W,
+        index: usize,
+        mut entry: ArchiveEntry,
+        data: &[u8],
+    ) -> Result<ArchiveEntry, Error> {
+        match self.entries.get(index) {
+            None => {
+                return Err(Error::parsing(format!(
+                    "Archive was created for {} entries, cannot add {:08x} at position {}",
+                    self.entries.len(),
+                    entry.identifier,
+                    index
+                )))
+            }
+            Some(Some(existing)) => {
+                return Err(Error::parsing(format!(
+                    "Position {} of the directory is already taken by {:08x}, cannot add {:08x}",
+                    index, existing.identifier, entry.identifier
+                )))
+            }
+            Some(None) => {}
+        }
+        entry.offset = self.offset(entry.identifier)?;
+
+        writer.write_all(data)?;
+        self.position += data.len() as u64;
+        self.entries[index] = Some(entry);
+        // Entries written at an index may have filled the following positions
+        while self
+            .entries
+            .get(self.next_free)
+            .is_some_and(|e| e.is_some())
+        {
+            self.next_free += 1;
+        }
+        Ok(entry)
+    }
+
+    fn write_padding<W: Write>(&mut self, writer: &mut W, data: &[u8]) -> Result<(), Error> {
+        writer.write_all(data)?;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    fn offset(&self, identifier: u32) -> Result<u32, Error> {
+        u32::try_from(self.position).map_err(|_| {
+            Error::parsing(format!(
+                "Archive is too large to add {:08x} at offset 0x{:x}",
+                identifier, self.position
+            ))
+        })
+    }
+
+    fn write_directory<W: Write + Seek>(
+        &self,
+        writer: &mut W,
+        write_directory_entry: impl Fn(&ArchiveEntry, &mut Vec<u8>),
+    ) -> Result<(), Error> {
+        let written = self.entries.iter().filter(|e| e.is_some()).count();
+        if written != self.entries.len() {
+            return Err(Error::parsing(format!(
+                "Archive was created for {} entries, but {} were written",
+                self.entries.len(),
+                written
+            )));
+        }
+
+        let mut directory: Vec<u8> = vec![];
+        for entry in self.entries.iter().flatten() {
+            write_directory_entry(entry, &mut directory);
+        }
+
+        writer.seek(SeekFrom::Start(self.directory_offset))?;
+        writer.write_all(&directory)?;
+        writer.seek(SeekFrom::Start(self.position))?;
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use super::{
+    read_directory, read_entry_data, size_field, ArchiveEntry, ArchiveKind, ArchiveReader,
+    ArchiveWriter, WriterState,
+};
+use crate::compression::CompressionFormat;
+use crate::error::{Error, ErrorKind};
+
+/// Reader for AUFS archives (.osa).
+#[derive(Debug)]
+pub struct OsaArchive<R> {
+    reader: R,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl<R: Read + Seek> OsaArchive<R> {
+    /// Parses the header and directory of the archive.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut buffer: [u8; 4] = [0; 4];
+
+        reader.seek(SeekFrom::Start(0))?;
+
+        // AUFS header
+        reader.read_exact(&mut buffer)?;
+        if buffer != [0x41, 0x55, 0x46, 0x53] {
+            return Err(Error::new(ErrorKind::BadMagic {
+                expected: "AUFS".to_string(),
+                found: buffer.to_vec(),
+            })
+            .at_offset(0));
+        }
+
+        // Number of files header
+        reader.read_exact(&mut buffer)?;
+        let number_of_files = u32::from_le_bytes(buffer);
+
+        let directory = read_directory(&mut reader, 8, 12, number_of_files)?;
+        let entries = directory
+            .chunks_exact(12)
+            .map(Self::parse_directory_entry)
+            .collect();
+
+        Ok(Self { reader, entries })
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn parse_directory_entry(directory_entry: &[u8]) -> ArchiveEntry {
+        let field =
+            |i: usize| u32::from_le_bytes(directory_entry[i * 4..i * 4 + 4].try_into().unwrap());
+
+        ArchiveEntry {
+            identifier: field(0),
+            offset: field(1),
+            size: field(2),
+            compressed_size: 0,
+            compression: CompressionFormat::None,
+        }
+    }
+}
+
+impl<R: Read + Seek> ArchiveReader for OsaArchive<R> {
+    fn kind(&self) -> ArchiveKind {
+        ArchiveKind::Osa
+    }
+
+    fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    fn read_raw(&mut self, entry: &ArchiveEntry) -> Result<Vec<u8>, Error> {
+        read_entry_data(&mut self.reader, entry)
+    }
+}
+
+/// Streaming writer for AUFS archives (.osa).
+///
+/// AUFS archives do not support compression, so all entries are stored as
+/// they are.
+#[derive(Debug)]
+pub struct OsaWriter<W> {
+    writer: W,
+    state: WriterState,
+}
+
+impl<W: Write + Seek> OsaWriter<W> {
+    /// Writes the header and reserves the directory for `number_of_entries`.
+    pub fn new(mut writer: W, number_of_entries: u32) -> Result<Self, Error> {
+        let mut header: Vec<u8> = vec![];
+        header.extend_from_slice(&[0x41, 0x55, 0x46, 0x53]);
+        header.extend_from_slice(&u32::to_le_bytes(number_of_entries));
+
+        let state = WriterState::new(&mut writer, &header, 12, number_of_entries)?;
+        Ok(Self { writer, state })
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek> ArchiveWriter for OsaWriter<W> {
+    fn write_entry(
+        &mut self,
+        identifier: u32,
+        data: &[u8],
+        _compressed: bool,
+    ) -> Result<ArchiveEntry, Error> {
+        let entry = ArchiveEntry {
+            identifier,
+            offset: 0,
+            size: size_field(identifier, data.len())?,
+            compressed_size: 0,
+            compression: CompressionFormat::None,
+        };
+        let index = self.state.next_index();
+        self.state.write_data(&mut self.writer, index, entry, data)
+    }
+
+    fn write_raw_entry(
+        &mut self,
+        entry: &ArchiveEntry,
+        data: &[u8],
+    ) -> Result<ArchiveEntry, Error> {
+        let index = self.state.next_index();
+        self.write_raw_entry_at(index, entry, data)
+    }
+
+    fn write_raw_entry_at(
+        &mut self,
+        index: usize,
+        entry: &ArchiveEntry,
+        data: &[u8],
+    ) -> Result<ArchiveEntry, Error> {
+        if entry.is_compressed() {
+            return Err(Error::parsing(format!(
+                "Entry {:08x} is compressed, which AUFS archives do not support",
+                entry.identifier
+            )));
+        }
+        let entry = ArchiveEntry {
+            identifier: entry.identifier,
+            offset: 0,
+            size: size_field(entry.identifier, data.len())?,
+            compressed_size: 0,
+            compression: CompressionFormat::None,
+        };
+        self.state.write_data(&mut self.writer, index, entry, data)
+    }
+
+    fn write_padding(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.state.write_padding(&mut self.writer, data)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.state
+            .write_directory(&mut self.writer, |entry, directory| {
+                directory.extend_from_slice(&u32::to_le_bytes(entry.identifier));
+                directory.extend_from_slice(&u32::to_le_bytes(entry.offset));
+                directory.extend_from_slice(&u32::to_le_bytes(entry.size));
+            })
+    }
+}
@@ -0,0 +1,656 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use super::{create, open, size_field, ArchiveEntry, ArchiveKind, ArchiveReader, Vt7aVersion};
+use crate::compression::{compress, default_level, CompressionFormat};
+use crate::error::{Error, ErrorKind};
+
+const MAGIC: &[u8; 4] = b"VT7P";
+const VERSION: u32 = 1;
+
+const VT7A: u32 = 0;
+const OSA: u32 = 1;
+
+const COPY: u32 = 0;
+const REPLACE: u32 = 1;
+const ADD: u32 = 2;
+
+/// The differences between two versions of an archive, as stored in a VT7P
+/// patch file.
+///
+/// A patch holds the layout of the modified archive: its header, the position
+/// of every entry in the directory and the order of their data, and the bytes
+/// between the entries. Entries which are stored the same way in the original
+/// archive are copied from it, so the patch only holds the bytes of the
+/// replaced and added entries. Applying the patch reproduces the modified
+/// archive byte for byte, and only works on the exact archive the patch was
+/// created from.
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::{BufReader, BufWriter};
+/// use vt7packer::archive::Patch;
+///
+/// let patch = Patch::create(
+///     BufReader::new(File::open("graphics_2x.vt7a")?),
+///     BufReader::new(File::open("graphics_2x.modded.vt7a")?),
+/// )?;
+/// patch.write(BufWriter::new(File::create("graphics_2x.vt7p")?))?;
+///
+/// let mut target = File::options()
+///     .read(true)
+///     .write(true)
+///     .create(true)
+///     .truncate(true)
+///     .open("graphics_2x.patched.vt7a")?;
+/// patch.apply(BufReader::new(File::open("graphics_2x.vt7a")?), &mut target)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// SHA-256 hash of the archive the patch applies to
+    pub base_hash: [u8; 32],
+    /// SHA-256 hash of the archive the patch creates
+    pub target_hash: [u8; 32],
+    /// Type and header fields of the modified archive
+    pub kind: ArchiveKind,
+    /// Bytes between the directory and the first entry
+    pub leading_data: Vec<u8>,
+    /// Entries of the modified archive in the order of their data
+    pub entries: Vec<PatchEntry>,
+    /// Identifiers of the entries of the original archive which the modified
+    /// archive no longer has
+    pub removed: Vec<u32>,
+}
+
+/// An entry of the modified archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchEntry {
+    /// Position of the entry in the directory
+    pub index: u32,
+    /// Directory entry, the offset follows from the layout
+    pub entry: ArchiveEntry,
+    pub data: PatchData,
+    /// Bytes following the entry which belong to no entry
+    pub padding: Vec<u8>,
+}
+
+/// Where the stored bytes of an entry of the modified archive come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchData {
+    /// Copied from the entry at this position of the original directory
+    Copy(u32),
+    /// Stored bytes of an entry whose identifier the original archive has,
+    /// but with other contents or compression
+    Replace(Vec<u8>),
+    /// Stored bytes of an entry the original archive does not have
+    Add(Vec<u8>),
+}
+
+impl Patch {
+    /// Compares the entries of an original and a modified archive and returns
+    /// the patch turning one into the other.
+    ///
+    /// Entries are copied from the original archive if it has an entry with
+    /// the same identifier and the same stored bytes, everything else is kept
+    /// in the patch as stored in the modified archive. Fails if entries of the
+    /// modified archive overlap, as its layout could not be reproduced.
+    pub fn create<R1: Read + Seek, R2: Read + Seek>(
+        original: R1,
+        modified: R2,
+    ) -> Result<Self, Error> {
+        Self::create_with_context(original, modified, |e| e, |e| e)
+    }
+
+    /// Like [`Patch::create`], but opens the archives from files. Errors name
+    /// the file which failed.
+    pub fn create_from_files(original_path: &Path, modified_path: &Path) -> Result<Self, Error> {
+        let open_file = |path: &Path| {
+            File::open(path)
+                .map(BufReader::new)
+                .map_err(|e| Error::from(e).with_path(path))
+        };
+        Self::create_with_context(
+            open_file(original_path)?,
+            open_file(modified_path)?,
+            |e| e.with_path(original_path),
+            |e| e.with_path(modified_path),
+        )
+    }
+
+    /// Implements [`Patch::create`], passing the errors of each archive
+    /// through its `context`.
+    fn create_with_context<R1: Read + Seek, R2: Read + Seek>(
+        mut original: R1,
+        mut modified: R2,
+        original_context: impl Fn(Error) -> Error,
+        modified_context: impl Fn(Error) -> Error,
+    ) -> Result<Self, Error> {
+        let base_hash = hash_archive(&mut original).map_err(&original_context)?;
+        let mut original = open(original).map_err(&original_context)?;
+        let target_hash = hash_archive(&mut modified).map_err(&modified_context)?;
+        let archive_size = modified
+            .seek(SeekFrom::End(0))
+            .map_err(|e| modified_context(e.into()))?;
+        let mut modified = open(modified).map_err(&modified_context)?;
+        let kind = modified.kind();
+
+        let original_entries: Vec<ArchiveEntry> = original.entries().to_vec();
+        let mut original_indices: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (index, entry) in original_entries.iter().enumerate() {
+            original_indices
+                .entry(entry.identifier)
+                .or_default()
+                .push(index);
+        }
+
+        // Entries without contents come first, so they keep the offset of the
+        // entry following them
+        let modified_entries = modified.entries().to_vec();
+        let mut order: Vec<usize> = (0..modified_entries.len()).collect();
+        order.sort_by_key(|&i| {
+            (
+                modified_entries[i].offset,
+                modified_entries[i].stored_size(),
+            )
+        });
+        let start = |i: usize| modified_entries[i].offset as u64;
+        let end = |i: usize| start(i) + modified_entries[i].stored_size() as u64;
+
+        let data_offset = kind.data_offset(modified_entries.len());
+        let first_offset = order.first().map(|&i| start(i)).unwrap_or(archive_size);
+        if first_offset < data_offset {
+            return Err(modified_context(Error::parsing(format!(
+                "Entry {:08x} starts within the directory, the archive cannot be reproduced",
+                modified_entries[order[0]].identifier
+            ))));
+        }
+        let first_identifier = order
+            .first()
+            .map(|&i| modified_entries[i].identifier)
+            .unwrap_or_default();
+        let leading_data = read_between(
+            modified.as_mut(),
+            first_identifier,
+            data_offset,
+            first_offset,
+        )
+        .map_err(&modified_context)?;
+
+        let mut entries = vec![];
+        for (position, &i) in order.iter().enumerate() {
+            let entry = modified_entries[i];
+            let stored = modified.read_raw(&entry).map_err(&modified_context)?;
+            let next_offset = order
+                .get(position + 1)
+                .map(|&j| start(j))
+                .unwrap_or(archive_size);
+            if next_offset < end(i) {
+                return Err(modified_context(Error::parsing(format!(
+                    "Entry {:08x} overlaps the following entry, the archive cannot be reproduced",
+                    entry.identifier
+                ))));
+            }
+
+            let candidates = original_indices
+                .get(&entry.identifier)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let mut copy = None;
+            for &index in candidates {
+                let original_entry = &original_entries[index];
+                if original_entry.size == entry.size
+                    && original_entry.compressed_size == entry.compressed_size
+                    && original_entry.compression == entry.compression
+                    && original
+                        .read_raw(original_entry)
+                        .map_err(&original_context)?
+                        == stored
+                {
+                    copy = Some(index as u32);
+                    break;
+                }
+            }
+            let padding = read_between(modified.as_mut(), entry.identifier, end(i), next_offset)
+                .map_err(&modified_context)?;
+            entries.push(PatchEntry {
+                index: i as u32,
+                entry: ArchiveEntry { offset: 0, ..entry },
+                data: match copy {
+                    Some(index) => PatchData::Copy(index),
+                    None if candidates.is_empty() => PatchData::Add(stored),
+                    None => PatchData::Replace(stored),
+                },
+                padding,
+            });
+        }
+
+        let mut modified_identifiers: HashSet<u32> = modified_entries
+            .iter()
+            .map(|entry| entry.identifier)
+            .collect();
+        let removed = original_entries
+            .iter()
+            .map(|entry| entry.identifier)
+            // Inserting marks the identifier, so duplicates are listed once
+            .filter(|identifier| modified_identifiers.insert(*identifier))
+            .collect();
+
+        Ok(Self {
+            base_hash,
+            target_hash,
+            kind,
+            leading_data,
+            entries,
+            removed,
+        })
+    }
+
+    /// Parses a VT7P patch file.
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        let mut input = Input {
+            data: &data,
+            position: 0,
+        };
+
+        let magic = input.take(4, "the header")?;
+        if magic != MAGIC {
+            return Err(Error::new(ErrorKind::BadMagic {
+                expected: "VT7P".to_string(),
+                found: magic.to_vec(),
+            })
+            .at_offset(0));
+        }
+        let version = input.u32("the header")?;
+        if version != VERSION {
+            return Err(Error::new(ErrorKind::UnsupportedVersion(version)).at_offset(4));
+        }
+        let base_hash: [u8; 32] = input.take(32, "the header")?.try_into().unwrap();
+        let target_hash: [u8; 32] = input.take(32, "the header")?.try_into().unwrap();
+
+        let offset = input.position as u64;
+        let archive_type = input.u32("the header")?;
+        let archive_version = input.u32("the header")?;
+        let timestamp = input.u32("the header")?;
+        let kind = match archive_type {
+            VT7A => ArchiveKind::Vt7a {
+                version: Vt7aVersion::try_from(archive_version)
+                    .map_err(|e| e.at_offset(offset + 4))?,
+                timestamp,
+            },
+            OSA => ArchiveKind::Osa,
+            _ => {
+                return Err(
+                    Error::parsing(format!("Unknown type of archive {}", archive_type))
+                        .at_offset(offset),
+                )
+            }
+        };
+        let leading_data = input.bytes("the header")?.to_vec();
+
+        let number_of_removed = input.u32("the removed entries")?;
+        let mut removed = vec![];
+        for _ in 0..number_of_removed {
+            removed.push(input.u32("the removed entries")?);
+        }
+
+        let number_of_entries = input.u32("the header")?;
+        let mut entries = vec![];
+        for _ in 0..number_of_entries {
+            let offset = input.position as u64;
+            let kind_of_data = input.u32("an entry")?;
+            let index = input.u32("an entry")?;
+            let identifier = input.u32("an entry")?;
+            let size = input.u32("an entry")?;
+            let compressed_size = input.u32("an entry")?;
+            let original_index = input.u32("an entry")?;
+            let stored = input.bytes("the data of an entry")?;
+            let padding = input.bytes("the padding of an entry")?.to_vec();
+
+            let entry = ArchiveEntry {
+                identifier,
+                offset: 0,
+                size,
+                compressed_size,
+                compression: match (kind, compressed_size) {
+                    (_, 0) | (ArchiveKind::Osa, _) => CompressionFormat::None,
+                    (ArchiveKind::Vt7a { version, .. }, _) => version.compression(),
+                },
+            };
+            // The stored size limits the decompression, so a corrupt patch
+            // cannot expand into more memory than the entry needs
+            let stored_data = || -> Result<Vec<u8>, Error> {
+                let mut data = vec![];
+                zstd::Decoder::new(stored)
+                    .and_then(|decoder| {
+                        decoder
+                            .take(entry.stored_size() as u64 + 1)
+                            .read_to_end(&mut data)
+                    })
+                    .map_err(|e| Error::from(e).with_resource(identifier).at_offset(offset))?;
+                if data.len() != entry.stored_size() as usize {
+                    let found = match data.len() > entry.stored_size() as usize {
+                        true => "more".to_string(),
+                        false => data.len().to_string(),
+                    };
+                    return Err(Error::parsing(format!(
+                        "Expected {} bytes, but found {}",
+                        entry.stored_size(),
+                        found
+                    ))
+                    .with_resource(identifier)
+                    .at_offset(offset));
+                }
+                Ok(data)
+            };
+            let data = match kind_of_data {
+                COPY => PatchData::Copy(original_index),
+                REPLACE => PatchData::Replace(stored_data()?),
+                ADD => PatchData::Add(stored_data()?),
+                _ => {
+                    return Err(
+                        Error::parsing(format!("Unknown type of entry {}", kind_of_data))
+                            .at_offset(offset),
+                    )
+                }
+            };
+            entries.push(PatchEntry {
+                index,
+                entry,
+                data,
+                padding,
+            });
+        }
+
+        Ok(Self {
+            base_hash,
+            target_hash,
+            kind,
+            leading_data,
+            entries,
+            removed,
+        })
+    }
+
+    /// Writes the patch as VT7P patch file.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&self.base_hash)?;
+        writer.write_all(&self.target_hash)?;
+
+        let (archive_type, archive_version, timestamp) = match self.kind {
+            ArchiveKind::Vt7a { version, timestamp } => (VT7A, version.into(), timestamp),
+            ArchiveKind::Osa => (OSA, 0, 0),
+        };
+        writer.write_all(&u32::to_le_bytes(archive_type))?;
+        writer.write_all(&u32::to_le_bytes(archive_version))?;
+        writer.write_all(&u32::to_le_bytes(timestamp))?;
+        write_bytes(&mut writer, &self.leading_data)?;
+
+        writer.write_all(&(self.removed.len() as u32).to_le_bytes())?;
+        for identifier in &self.removed {
+            writer.write_all(&identifier.to_le_bytes())?;
+        }
+
+        writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        for patch_entry in &self.entries {
+            let entry = &patch_entry.entry;
+            let (kind_of_data, original_index, stored): (u32, u32, &[u8]) = match &patch_entry.data
+            {
+                PatchData::Copy(index) => (COPY, *index, &[]),
+                PatchData::Replace(stored) => (REPLACE, 0, stored),
+                PatchData::Add(stored) => (ADD, 0, stored),
+            };
+            let stored = match kind_of_data {
+                COPY => vec![],
+                _ => compress(
+                    stored,
+                    CompressionFormat::Zstd,
+                    default_level(CompressionFormat::Zstd),
+                )?,
+            };
+            writer.write_all(&kind_of_data.to_le_bytes())?;
+            writer.write_all(&patch_entry.index.to_le_bytes())?;
+            writer.write_all(&entry.identifier.to_le_bytes())?;
+            writer.write_all(&entry.size.to_le_bytes())?;
+            writer.write_all(&entry.compressed_size.to_le_bytes())?;
+            writer.write_all(&original_index.to_le_bytes())?;
+            writer.write_all(&size_field(entry.identifier, stored.len())?.to_le_bytes())?;
+            writer.write_all(&stored)?;
+            write_bytes(&mut writer, &patch_entry.padding)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the modified archive into `target`, copying the unchanged
+    /// entries from `original`. Fails if `original` is not the archive the
+    /// patch was created from, or if the written archive does not match the
+    /// modified one.
+    pub fn apply<R: Read + Seek, W: Read + Write + Seek>(
+        &self,
+        mut original: R,
+        mut target: W,
+    ) -> Result<(), Error> {
+        let hash = hash_archive(&mut original)?;
+        if hash != self.base_hash {
+            return Err(Error::parsing(format!(
+                "The patch was created for another archive (SHA-256 {}, but found {})",
+                hex(&self.base_hash),
+                hex(&hash)
+            )));
+        }
+        let mut original = open(original)?;
+        let original_entries = original.entries().to_vec();
+
+        let mut buffered = BufWriter::new(&mut target);
+        let mut writer = create(&mut buffered, self.kind, self.entries.len() as u32)?;
+        writer.write_padding(&self.leading_data)?;
+        for patch_entry in &self.entries {
+            let entry = &patch_entry.entry;
+            let stored = match &patch_entry.data {
+                PatchData::Copy(index) => {
+                    let Some(original_entry) = original_entries.get(*index as usize) else {
+                        return Err(Error::parsing(format!(
+                            "Entry {:08x} is copied from position {}, but the archive has {} entries",
+                            entry.identifier,
+                            index,
+                            original_entries.len()
+                        )));
+                    };
+                    original.read_raw(original_entry)?
+                }
+                PatchData::Replace(stored) | PatchData::Add(stored) => stored.clone(),
+            };
+            writer
+                .write_raw_entry_at(patch_entry.index as usize, entry, &stored)
+                .map_err(|e| e.with_resource(entry.identifier))?;
+            writer.write_padding(&patch_entry.padding)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        buffered.flush()?;
+        drop(buffered);
+
+        let hash = hash_archive(&mut target)?;
+        if hash != self.target_hash {
+            return Err(Error::parsing(format!(
+                "The patched archive differs from the modified one (SHA-256 {}, but found {})",
+                hex(&self.target_hash),
+                hex(&hash)
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Reads the fields of a patch file one after the other.
+struct Input<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Input<'a> {
+    fn take(&mut self, length: usize, what: &str) -> Result<&'a [u8], Error> {
+        let end = self.position + length;
+        if end > self.data.len() {
+            return Err(Error::new(ErrorKind::Truncated(format!(
+                "The patch ends within {}",
+                what
+            )))
+            .at_offset(self.position as u64));
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self, what: &str) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4, what)?.try_into().unwrap()))
+    }
+
+    /// Reads bytes preceded by their length.
+    fn bytes(&mut self, what: &str) -> Result<&'a [u8], Error> {
+        let length = self.u32(what)?;
+        self.take(length as usize, what)
+    }
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<(), Error> {
+    let length = u32::try_from(bytes.len())
+        .map_err(|_| Error::parsing(format!("{} bytes do not fit into a patch", bytes.len())))?;
+    writer.write_all(&length.to_le_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+/// Reads the bytes from `start` to `end` of an archive which belong to no
+/// entry. Errors name the entry next to them.
+fn read_between(
+    archive: &mut dyn ArchiveReader,
+    identifier: u32,
+    start: u64,
+    end: u64,
+) -> Result<Vec<u8>, Error> {
+    if start == end {
+        return Ok(vec![]);
+    }
+    let size = u32::try_from(end - start)
+        .map_err(|_| Error::parsing(format!("{} bytes do not fit into a patch", end - start)))?;
+    let range = ArchiveEntry {
+        identifier,
+        offset: start as u32,
+        size,
+        compressed_size: 0,
+        compression: CompressionFormat::None,
+    };
+    archive.read_raw(&range)
+}
+
+/// Returns the SHA-256 hash of a whole archive and rewinds the reader.
+fn hash_archive<R: Read + Seek>(reader: &mut R) -> Result<[u8; 32], Error> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher)?;
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(hasher.finalize().into())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Builds a VT7A archive with the entries given as identifier, data and
+    /// whether to compress them, each followed by `padding`.
+    fn archive(timestamp: u32, entries: &[(u32, &[u8], bool)], padding: &[u8]) -> Vec<u8> {
+        let kind = ArchiveKind::Vt7a {
+            version: Vt7aVersion::Three,
+            timestamp,
+        };
+        let mut data = Cursor::new(vec![]);
+        let mut writer = create(&mut data, kind, entries.len() as u32).unwrap();
+        for (identifier, contents, compressed) in entries {
+            writer
+                .write_entry(*identifier, contents, *compressed)
+                .unwrap();
+            writer.write_padding(padding).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+        data.into_inner()
+    }
+
+    fn original() -> Vec<u8> {
+        archive(
+            1,
+            &[
+                (0x0a, b"unchanged entry, unchanged entry", true),
+                (0x0b, b"old text", false),
+                (0x0c, b"removed entry", true),
+            ],
+            &[],
+        )
+    }
+
+    fn modified() -> Vec<u8> {
+        archive(
+            2,
+            &[
+                (0x0d, b"added entry", false),
+                (0x0a, b"unchanged entry, unchanged entry", true),
+                (0x0b, b"new text", true),
+            ],
+            &[0; 3],
+        )
+    }
+
+    #[test]
+    fn applying_a_written_patch_reproduces_the_modified_archive() {
+        let patch = Patch::create(Cursor::new(original()), Cursor::new(modified())).unwrap();
+        assert_eq!(patch.removed, vec![0x0c]);
+        let data: Vec<(u32, &PatchData)> = patch
+            .entries
+            .iter()
+            .map(|e| (e.entry.identifier, &e.data))
+            .collect();
+        assert!(matches!(
+            data[..],
+            [
+                (0x0d, PatchData::Add(_)),
+                (0x0a, PatchData::Copy(0)),
+                (0x0b, PatchData::Replace(_))
+            ]
+        ));
+
+        let mut written = vec![];
+        patch.write(&mut written).unwrap();
+        let read = Patch::read(written.as_slice()).unwrap();
+        assert_eq!(read, patch);
+
+        let mut target = Cursor::new(vec![]);
+        read.apply(Cursor::new(original()), &mut target).unwrap();
+        assert_eq!(target.into_inner(), modified());
+    }
+
+    #[test]
+    fn applying_to_another_archive_fails() {
+        let patch = Patch::create(Cursor::new(original()), Cursor::new(modified())).unwrap();
+        let mut target = Cursor::new(vec![]);
+        let result = patch.apply(Cursor::new(modified()), &mut target);
+        assert!(result.is_err());
+        assert!(target.into_inner().is_empty());
+    }
+}
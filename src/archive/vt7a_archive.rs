@@ -0,0 +1,315 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use log::trace;
+
+use super::{
+    compress_entry, read_directory, read_entry_data, ArchiveEntry, ArchiveKind, ArchiveReader,
+    ArchiveWriter, WriterState,
+};
+use crate::compression::CompressionFormat;
+use crate::error::{Error, ErrorKind};
+
+/// Version of a VT7A archive, which decides the compression algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vt7aVersion {
+    /// Original games, compressed with zlib
+    Two,
+    /// Reforged, compressed with zstd
+    Three,
+}
+
+impl Vt7aVersion {
+    /// Returns the algorithm compressed entries use in this version.
+    pub fn compression(&self) -> CompressionFormat {
+        match self {
+            Self::Two => CompressionFormat::Zlib,
+            Self::Three => CompressionFormat::Zstd,
+        }
+    }
+}
+
+impl TryFrom<u32> for Vt7aVersion {
+    type Error = Error;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            2 => Ok(Self::Two),
+            3 => Ok(Self::Three),
+            _ => Err(Error::new(ErrorKind::UnsupportedVersion(value))),
+        }
+    }
+}
+
+impl From<Vt7aVersion> for u32 {
+    fn from(value: Vt7aVersion) -> Self {
+        match value {
+            Vt7aVersion::Two => 2,
+            Vt7aVersion::Three => 3,
+        }
+    }
+}
+
+/// Formats the timestamp of a VT7A header as a UTC date like
+/// `2024-03-19 14:02:11 UTC`.
+///
+/// Returns `None` if the value is not plausible for a Unix timestamp of an
+/// archive, i.e. before 2000 or after 2037.
+pub fn format_timestamp(timestamp: u32) -> Option<String> {
+    const YEAR_2000: u32 = 946_684_800;
+    const YEAR_2038: u32 = 2_145_916_800;
+    if !(YEAR_2000..YEAR_2038).contains(&timestamp) {
+        return None;
+    }
+
+    let days = timestamp / 86_400;
+    let seconds = timestamp % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    Some(format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    ))
+}
+
+/// Converts days since 1970-01-01 into year, month and day of the Gregorian
+/// calendar (after <https://howardhinnant.github.io/date_algorithms.html>).
+fn civil_from_days(days: u32) -> (u32, u32, u32) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u32::from(month <= 2);
+    (year, month, day)
+}
+
+/// Reader for VT7A archives (.vt7a).
+#[derive(Debug)]
+pub struct Vt7aArchive<R> {
+    reader: R,
+    version: Vt7aVersion,
+    timestamp: u32,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl<R: Read + Seek> Vt7aArchive<R> {
+    /// Parses the header and directory of the archive.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut buffer: [u8; 4] = [0; 4];
+
+        reader.seek(SeekFrom::Start(0))?;
+
+        // VT7A header
+        reader.read_exact(&mut buffer)?;
+        if buffer != [0x56, 0x54, 0x37, 0x41] {
+            return Err(Error::new(ErrorKind::BadMagic {
+                expected: "VT7A".to_string(),
+                found: buffer.to_vec(),
+            })
+            .at_offset(0));
+        }
+        trace!("VT7A header: {:?}", buffer);
+
+        // Version header
+        reader.read_exact(&mut buffer)?;
+        let version =
+            Vt7aVersion::try_from(u32::from_le_bytes(buffer)).map_err(|e| e.at_offset(4))?;
+        trace!("VT7A Version: {:?}", version);
+
+        // Timestamp header
+        reader.read_exact(&mut buffer)?;
+        let timestamp = u32::from_le_bytes(buffer);
+        trace!("VT7A Timestamp: {:#x}", timestamp);
+
+        // Number of files header
+        reader.read_exact(&mut buffer)?;
+        let number_of_files = u32::from_le_bytes(buffer);
+        trace!("VT7A NumOfFiles: {}", number_of_files);
+
+        let directory = read_directory(&mut reader, 16, 16, number_of_files)?;
+        let entries = directory
+            .chunks_exact(16)
+            .map(|directory_entry| Self::parse_directory_entry(directory_entry, version))
+            .collect();
+
+        Ok(Self {
+            reader,
+            version,
+            timestamp,
+            entries,
+        })
+    }
+
+    /// Returns the version of the archive.
+    pub fn version(&self) -> Vt7aVersion {
+        self.version
+    }
+
+    /// Returns the third field of the archive header, which most likely is
+    /// the time of creation, see [`format_timestamp`].
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn parse_directory_entry(directory_entry: &[u8], version: Vt7aVersion) -> ArchiveEntry {
+        let field =
+            |i: usize| u32::from_le_bytes(directory_entry[i * 4..i * 4 + 4].try_into().unwrap());
+
+        let identifier = field(0);
+        trace!("-   VT7A File Identifier: {:#x}", identifier);
+        let offset = field(1);
+        trace!("    VT7A File Offset: {:#x}", offset);
+        let size = field(2);
+        trace!("    VT7A File Size Uncompressed: {:#x}", size);
+        let compressed_size = field(3);
+        trace!("    VT7A File Size Compressed: {:#x}", compressed_size);
+
+        ArchiveEntry {
+            identifier,
+            offset,
+            size,
+            compressed_size,
+            compression: if compressed_size != 0 {
+                version.compression()
+            } else {
+                CompressionFormat::None
+            },
+        }
+    }
+}
+
+impl<R: Read + Seek> ArchiveReader for Vt7aArchive<R> {
+    fn kind(&self) -> ArchiveKind {
+        ArchiveKind::Vt7a {
+            version: self.version,
+            timestamp: self.timestamp,
+        }
+    }
+
+    fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    fn read_raw(&mut self, entry: &ArchiveEntry) -> Result<Vec<u8>, Error> {
+        read_entry_data(&mut self.reader, entry)
+    }
+}
+
+/// Streaming writer for VT7A archives (.vt7a).
+#[derive(Debug)]
+pub struct Vt7aWriter<W> {
+    writer: W,
+    version: Vt7aVersion,
+    state: WriterState,
+}
+
+impl<W: Write + Seek> Vt7aWriter<W> {
+    /// Writes the header and reserves the directory for `number_of_entries`.
+    pub fn new(
+        mut writer: W,
+        version: Vt7aVersion,
+        timestamp: u32,
+        number_of_entries: u32,
+    ) -> Result<Self, Error> {
+        let mut header: Vec<u8> = vec![];
+        header.extend_from_slice(&[0x56, 0x54, 0x37, 0x41]);
+        header.extend_from_slice(&u32::to_le_bytes(version.into()));
+        header.extend_from_slice(&u32::to_le_bytes(timestamp));
+        header.extend_from_slice(&u32::to_le_bytes(number_of_entries));
+
+        let state = WriterState::new(&mut writer, &header, 16, number_of_entries)?;
+        Ok(Self {
+            writer,
+            version,
+            state,
+        })
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek> ArchiveWriter for Vt7aWriter<W> {
+    fn write_entry(
+        &mut self,
+        identifier: u32,
+        data: &[u8],
+        compressed: bool,
+    ) -> Result<ArchiveEntry, Error> {
+        let compression = match compressed {
+            true => self.version.compression(),
+            false => CompressionFormat::None,
+        };
+        let (entry, stored_data) = compress_entry(identifier, data, compression)?;
+        let index = self.state.next_index();
+        self.state
+            .write_data(&mut self.writer, index, entry, &stored_data)
+    }
+
+    fn write_raw_entry(
+        &mut self,
+        entry: &ArchiveEntry,
+        data: &[u8],
+    ) -> Result<ArchiveEntry, Error> {
+        let index = self.state.next_index();
+        self.write_raw_entry_at(index, entry, data)
+    }
+
+    fn write_raw_entry_at(
+        &mut self,
+        index: usize,
+        entry: &ArchiveEntry,
+        data: &[u8],
+    ) -> Result<ArchiveEntry, Error> {
+        if entry.is_compressed() && entry.compression != self.version.compression() {
+            return Err(Error::parsing(format!(
+                "Entry {:08x} is compressed with {:?}, but the archive uses {:?}",
+                entry.identifier,
+                entry.compression,
+                self.version.compression()
+            )));
+        }
+        if entry.stored_size() as usize != data.len() {
+            return Err(Error::parsing(format!(
+                "Entry {:08x} should have {} stored bytes, but has {}",
+                entry.identifier,
+                entry.stored_size(),
+                data.len()
+            )));
+        }
+        self.state.write_data(&mut self.writer, index, *entry, data)
+    }
+
+    fn write_padding(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.state.write_padding(&mut self.writer, data)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.state
+            .write_directory(&mut self.writer, |entry, directory| {
+                directory.extend_from_slice(&u32::to_le_bytes(entry.identifier));
+                directory.extend_from_slice(&u32::to_le_bytes(entry.offset));
+                directory.extend_from_slice(&u32::to_le_bytes(entry.size));
+                directory.extend_from_slice(&u32::to_le_bytes(entry.compressed_size));
+            })
+    }
+}
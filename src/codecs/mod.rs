@@ -2,6 +2,7 @@ mod ogg_codec;
 mod old_engine_codec;
 mod osa_codec;
 mod raw_codec;
+mod registry;
 mod save_codec;
 mod sword_text_codec;
 mod ttf_codec;
@@ -11,91 +12,172 @@ mod webm_codec;
 mod webp_codec;
 mod xml_codec;
 
-use crate::{
-    error::{DecodingError, EncodingError},
-    resource::Resource,
+use crate::{error::Error, resource::Resource};
+use std::{
+    fmt::Display,
+    fs,
+    io::{Seek, Write},
+    path::Path,
 };
-use log::debug;
-use std::{fmt::Display, path::Path};
 
+pub use ogg_codec::OggCodec;
+pub use old_engine_codec::OldEngineCodec;
+pub use osa_codec::OsaCodec;
+pub use raw_codec::RawCodec;
+pub use registry::CodecRegistry;
+pub use save_codec::SaveCodec;
+pub(crate) use save_codec::SaveData;
+pub use sword_text_codec::{SwordTextCodec, TextLine};
+pub use ttf_codec::TtfCodec;
+pub use txt_codec::TxtCodec;
+pub use vt7a_codec::{Vt7aCodec, Vt7aTimestamp, Vt7aVersion};
+pub use webm_codec::WebmCodec;
+pub use webp_codec::WebpCodec;
+pub use xml_codec::XmlCodec;
+
+/// Converts a resource from its game format into a more useful format.
 pub trait Decoder {
+    /// Returns how well this decoder fits the resource, the highest score wins.
     fn matches_decoder(&self, resource: &Resource) -> usize;
-    fn decode(&self, resource: &mut Resource) -> Result<(), DecodingError>;
+    /// Decodes the resource in place, filling `subresources` for containers.
+    ///
+    /// Containers decode their subresources through `registry`.
+    fn decode(&self, resource: &mut Resource, registry: &CodecRegistry) -> Result<(), Error>;
 }
 
+/// Converts a decoded resource back into its game format.
 pub trait Encoder {
+    /// Returns how well this encoder fits the resource, the highest score wins.
     fn matches_encoder(&self, resource: &Resource) -> usize;
-    fn load_subresources(&self, path: &Path, resource: &mut Resource) -> Result<(), EncodingError>;
-    fn encode(&self, resource: &mut Resource) -> Result<(), EncodingError>;
+    /// Loads the files referenced by a decoded container from `path`.
+    fn load_subresources(
+        &self,
+        path: &Path,
+        resource: &mut Resource,
+        registry: &CodecRegistry,
+    ) -> Result<(), Error>;
+    /// Encodes the resource in place.
+    ///
+    /// Containers encode their subresources through `registry`.
+    fn encode(&self, resource: &mut Resource, registry: &CodecRegistry) -> Result<(), Error>;
+    /// Loads the subresources from `path`, encodes the resource and writes the
+    /// result into `sink`, leaving `resource.data` empty.
+    ///
+    /// By default everything is loaded into memory first. Archive codecs
+    /// override this to load, encode and write one entry at a time.
+    fn encode_into(
+        &self,
+        path: &Path,
+        resource: &mut Resource,
+        registry: &CodecRegistry,
+        sink: &mut dyn WriteSeek,
+    ) -> Result<(), Error> {
+        self.load_subresources(path, resource, registry)?;
+        self.encode(resource, registry)?;
+        sink.write_all(&resource.data)?;
+        resource.data.clear();
+        Ok(())
+    }
 }
 
-pub trait Codec: Encoder + Decoder + Display {}
-impl<T> Codec for T where T: Encoder + Decoder + Display {}
+/// Output of [`Encoder::encode_into`].
+pub trait WriteSeek: Write + Seek {}
+impl<T> WriteSeek for T where T: Write + Seek {}
 
-fn get_codecs() -> Vec<Box<dyn Codec>> {
-    vec![
-        Box::new(old_engine_codec::OldEngineCodec {}),
-        Box::new(ogg_codec::OggCodec {}),
-        Box::new(osa_codec::OsaCodec {}),
-        Box::new(raw_codec::RawCodec {}),
-        Box::new(save_codec::SaveCodec {}),
-        Box::new(sword_text_codec::SwordTextCodec {}),
-        Box::new(ttf_codec::TtfCodec {}),
-        Box::new(txt_codec::TxtCodec {}),
-        Box::new(vt7a_codec::Vt7aCodec {}),
-        Box::new(webm_codec::WebmCodec {}),
-        Box::new(webp_codec::WebpCodec {}),
-        Box::new(xml_codec::XmlCodec {}),
-    ]
-}
+/// A type that can both decode and encode a resource.
+///
+/// Codecs are shared between threads, as archive entries are decoded and
+/// encoded in parallel.
+pub trait Codec: Encoder + Decoder + Display + Send + Sync {}
+impl<T> Codec for T where T: Encoder + Decoder + Display + Send + Sync {}
 
-pub fn decode(resource: &mut Resource) -> Result<(), DecodingError> {
-    let decoders = get_codecs();
-    let mut best_match = 0;
-    let mut best_decoder = decoders.last().unwrap();
+/// Checks the manifest of a decoded archive against the files in its folder.
+///
+/// Identifiers listed more than once are reported. Returns identifier and
+/// name of every file in `dirpath` which is not listed, sorted by name. Files
+/// whose identifier collides with a listed entry are reported and skipped.
+fn find_new_files(dirpath: &Path, listed: &[(u32, &str)]) -> Result<Vec<(u32, String)>, Error> {
+    let mut identifiers: Vec<u32> = listed.iter().map(|(identifier, _)| *identifier).collect();
+    identifiers.sort_unstable();
+    for duplicates in identifiers.chunk_by(|a, b| a == b).filter(|d| d.len() > 1) {
+        log::warn!(
+            "{}: identifier {:08x} is listed {} times in the manifest",
+            dirpath.display(),
+            duplicates[0],
+            duplicates.len()
+        );
+    }
 
-    for decoder in &decoders {
-        let current_match = decoder.matches_decoder(resource);
-        if current_match > best_match {
-            best_match = current_match;
-            best_decoder = decoder;
+    if !dirpath.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut filenames = vec![];
+    for dir_entry in fs::read_dir(dirpath).map_err(|e| Error::from(e).with_path(dirpath))? {
+        let dir_entry = dir_entry.map_err(|e| Error::from(e).with_path(dirpath))?;
+        let filename = dir_entry.file_name().to_string_lossy().to_string();
+        if dir_entry.path().is_file()
+            && !filename.starts_with('.')
+            && !listed
+                .iter()
+                .any(|(_, listed_name)| *listed_name == filename)
+        {
+            filenames.push(filename);
         }
     }
+    filenames.sort();
 
-    debug!("{}: {}", best_decoder, resource);
-    best_decoder.decode(resource)
-}
-
-pub fn load_subresources(path: &Path, resource: &mut Resource) -> Result<(), EncodingError> {
-    let encoders = get_codecs();
-    let mut best_match = 0;
-    let mut best_encoder = encoders.last().unwrap();
-
-    for encoder in &encoders {
-        let current_match = encoder.matches_encoder(resource);
-        if current_match > best_match {
-            best_match = current_match;
-            best_encoder = encoder;
+    let mut new_files: Vec<(u32, String)> = vec![];
+    for filename in filenames {
+        let mut resource = Resource::default();
+        if resource.parse_filename(&filename).is_err() {
+            log::warn!(
+                "{}: ignoring {}, its name does not start with an identifier",
+                dirpath.display(),
+                filename
+            );
+            continue;
         }
+        if let Some((_, listed_name)) = listed
+            .iter()
+            .find(|(identifier, _)| *identifier == resource.identifier)
+        {
+            log::warn!(
+                "{}: ignoring {}, its identifier collides with {}",
+                dirpath.display(),
+                filename,
+                listed_name
+            );
+            continue;
+        }
+        if let Some((_, new_name)) = new_files
+            .iter()
+            .find(|(identifier, _)| *identifier == resource.identifier)
+        {
+            log::warn!(
+                "{}: ignoring {}, its identifier collides with {}",
+                dirpath.display(),
+                filename,
+                new_name
+            );
+            continue;
+        }
+        new_files.push((resource.identifier, filename));
     }
-
-    debug!("{}: {}", best_encoder, resource);
-    best_encoder.load_subresources(path, resource)
+    Ok(new_files)
 }
 
-pub fn encode(resource: &mut Resource) -> Result<(), EncodingError> {
-    let encoders = get_codecs();
-    let mut best_match = 0;
-    let mut best_encoder = encoders.last().unwrap();
+/// Decodes a resource with the best matching built-in codec.
+pub fn decode(resource: &mut Resource) -> Result<(), Error> {
+    CodecRegistry::default().decode(resource)
+}
 
-    for encoder in &encoders {
-        let current_match = encoder.matches_encoder(resource);
-        if current_match > best_match {
-            best_match = current_match;
-            best_encoder = encoder;
-        }
-    }
+/// Loads the subresources of a decoded resource from `path` with the best
+/// matching built-in codec.
+pub fn load_subresources(path: &Path, resource: &mut Resource) -> Result<(), Error> {
+    CodecRegistry::default().load_subresources(path, resource)
+}
 
-    debug!("{}: {}", best_encoder, resource);
-    best_encoder.encode(resource)
+/// Encodes a resource with the best matching built-in codec.
+pub fn encode(resource: &mut Resource) -> Result<(), Error> {
+    CodecRegistry::default().encode(resource)
 }
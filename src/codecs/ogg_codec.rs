@@ -1,9 +1,10 @@
 use std::fmt::Display;
 
-use super::{Decoder, Encoder};
-use crate::error::DecodingError;
+use super::{CodecRegistry, Decoder, Encoder};
+use crate::error::Error;
 use crate::resource::Resource;
 
+/// Recognizes Ogg Vorbis audio files.
 #[derive(Debug)]
 pub struct OggCodec;
 
@@ -21,7 +22,7 @@ impl Decoder for OggCodec {
         0
     }
 
-    fn decode(&self, resource: &mut Resource) -> Result<(), DecodingError> {
+    fn decode(&self, resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         resource.extension = Some("ogg".to_string());
         Ok(())
     }
@@ -39,11 +40,12 @@ impl Encoder for OggCodec {
         &self,
         _path: &std::path::Path,
         _resource: &mut Resource,
-    ) -> Result<(), crate::error::EncodingError> {
+        _registry: &CodecRegistry,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
-    fn encode(&self, _resource: &mut Resource) -> Result<(), crate::error::EncodingError> {
+    fn encode(&self, _resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         Ok(())
     }
 }
@@ -1,10 +1,11 @@
 use core::str;
 use std::fmt::Display;
 
-use super::{Decoder, Encoder};
-use crate::error::DecodingError;
+use super::{CodecRegistry, Decoder, Encoder};
+use crate::error::Error;
 use crate::resource::Resource;
 
+/// Recognizes resources from the older Virtual Theatre engine.
 #[derive(Debug)]
 pub struct OldEngineCodec;
 
@@ -27,7 +28,7 @@ impl Decoder for OldEngineCodec {
         0
     }
 
-    fn decode(&self, resource: &mut Resource) -> Result<(), DecodingError> {
+    fn decode(&self, resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         resource.extension = Some("oldengine".to_string());
         let format_type = str::from_utf8(&resource.data[0..6])
             .expect("Decoder should never be called on a non-string header")
@@ -60,11 +61,12 @@ impl Encoder for OldEngineCodec {
         &self,
         _path: &std::path::Path,
         _resource: &mut Resource,
-    ) -> Result<(), crate::error::EncodingError> {
+        _registry: &CodecRegistry,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
-    fn encode(&self, _resource: &mut Resource) -> Result<(), crate::error::EncodingError> {
+    fn encode(&self, _resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         Ok(())
     }
 }
@@ -1,11 +1,13 @@
 use std::fmt::Display;
-use std::io::{Cursor, Read};
+use std::io::{self, Cursor};
+use std::path::Path;
 
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use super::{Decoder, Encoder};
-use crate::codecs;
-use crate::error::DecodingError;
+use super::{find_new_files, CodecRegistry, Decoder, Encoder, WriteSeek};
+use crate::archive::{ArchiveReader, ArchiveWriter, OsaArchive, OsaWriter};
+use crate::error::Error;
 use crate::resource::Resource;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -14,8 +16,13 @@ pub struct ResourceItem {
     filename: String,
 }
 
-#[derive(Debug)]
-pub struct OsaCodec;
+/// Unpacks and packs AUFS archives (.osa).
+#[derive(Debug, Default)]
+pub struct OsaCodec {
+    /// Also pack files from the folder of a decoded archive which are not
+    /// listed in its manifest, instead of only reporting them
+    pub include_new_files: bool,
+}
 
 impl Display for OsaCodec {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -31,38 +38,38 @@ impl Decoder for OsaCodec {
         0
     }
 
-    fn decode(&self, resource: &mut Resource) -> Result<(), DecodingError> {
-        let mut resources: Vec<Resource> = vec![];
-        let mut resource_items: Vec<ResourceItem> = vec![];
-        let mut cursor = Cursor::new(&resource.data);
-        let mut buffer: [u8; 4] = [0; 4];
-
-        cursor.set_position(0);
-
-        // AUFS header
-        cursor.read_exact(&mut buffer)?;
-        if buffer != [0x41, 0x55, 0x46, 0x53] {
-            return Err(DecodingError::ParsingError(
-                "Archive header is missing AUFS bytes".to_string(),
-            ));
-        }
-
-        // Number of files header
-        cursor.read_exact(&mut buffer)?;
-        let number_of_files = u32::from_le_bytes(buffer);
-        let mut directory_entry_buffer: [u8; 12] = [0; 12];
-
-        // Parse directory and extract files
-        for _ in 0..number_of_files {
-            cursor.read_exact(&mut directory_entry_buffer)?;
-            let mut res = Self::decode_single_resource(resource, directory_entry_buffer)?;
-            codecs::decode(&mut res)?;
-            resource_items.push(ResourceItem {
+    fn decode(&self, resource: &mut Resource, registry: &CodecRegistry) -> Result<(), Error> {
+        let mut archive = OsaArchive::new(Cursor::new(&resource.data))?;
+
+        // Extract files
+        let resources = archive
+            .entries()
+            .to_vec()
+            .into_iter()
+            .map(|entry| {
+                Ok(Resource {
+                    identifier: entry.identifier,
+                    data: archive.read(&entry)?,
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?
+            .into_par_iter()
+            .map(|mut res| {
+                registry
+                    .decode(&mut res)
+                    .map_err(|e| e.with_resource(res.identifier))?;
+                Ok(res)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let resource_items: Vec<ResourceItem> = resources
+            .iter()
+            .map(|res| ResourceItem {
                 identifier: res.identifier,
                 filename: res.get_filename(),
-            });
-            resources.push(res);
-        }
+            })
+            .collect();
 
         let serialized_lines = serde_json::to_string_pretty(&resource_items).unwrap();
         resource.data = serialized_lines.as_bytes().to_vec();
@@ -74,36 +81,70 @@ impl Decoder for OsaCodec {
 }
 
 impl OsaCodec {
-    fn decode_single_resource(
+    /// Reads the manifest of a decoded archive and adds the new files from its
+    /// folder if requested.
+    fn read_manifest(
+        &self,
         resource: &Resource,
-        directory_entry: [u8; 12],
-    ) -> Result<Resource, DecodingError> {
-        let mut subresource = Resource::default();
-        let mut resource_cursor = Cursor::new(&resource.data);
-        let mut directory_entry_cursor = Cursor::new(&directory_entry);
-        let mut buffer: [u8; 4] = [0; 4];
-
-        // Identifier
-        directory_entry_cursor.read_exact(&mut buffer)?;
-        let identifier = u32::from_le_bytes(buffer);
-        subresource.identifier = identifier;
-
-        // Offset
-        directory_entry_cursor.read_exact(&mut buffer)?;
-        let offset = u32::from_le_bytes(buffer);
-
-        // Length
-        directory_entry_cursor.read_exact(&mut buffer)?;
-        let length = u32::from_le_bytes(buffer);
-
-        // Read data
-        resource_cursor.set_position(offset as u64);
-        resource_cursor
-            .take(length as u64)
-            .read_to_end(&mut subresource.data)?;
+        resource_dirpath: &Path,
+    ) -> Result<Vec<ResourceItem>, Error> {
+        let mut mapper: Vec<ResourceItem> = serde_json::from_slice(&resource.data)?;
+        let listed: Vec<(u32, &str)> = mapper
+            .iter()
+            .map(|item| (item.identifier, item.filename.as_str()))
+            .collect();
+
+        for (identifier, filename) in find_new_files(resource_dirpath, &listed)? {
+            if self.include_new_files {
+                log::info!(
+                    "Adding new file {}",
+                    resource_dirpath.join(&filename).display()
+                );
+                mapper.push(ResourceItem {
+                    identifier,
+                    filename,
+                });
+            } else {
+                log::warn!(
+                    "Skipping {}, it is not listed in the manifest",
+                    resource_dirpath.join(&filename).display()
+                );
+            }
+        }
+        Ok(mapper)
+    }
 
+    fn load_subresource(
+        resource_dirpath: &Path,
+        resource_item: &ResourceItem,
+        registry: &CodecRegistry,
+    ) -> Result<Resource, Error> {
+        let resource_path = resource_dirpath.join(&resource_item.filename);
+
+        let mut subresource = Resource::load_from(&resource_path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                Error::manifest_mismatch(format!("File {} not found", &resource_item.filename))
+                    .with_resource(resource_item.identifier)
+            }
+            _ => Error::from(e).with_path(&resource_path),
+        })?;
+        if subresource.identifier != resource_item.identifier {
+            return Err(Error::manifest_mismatch(format!(
+                "File {} does not belong to identifier {:08x}",
+                &resource_item.filename, resource_item.identifier
+            )));
+        }
+        registry
+            .load_subresources(resource_dirpath, &mut subresource)
+            .map_err(|e| e.with_resource(resource_item.identifier))?;
         Ok(subresource)
     }
+
+    fn finish_encoding(resource: &mut Resource) {
+        resource.extension = Some("osa".to_string());
+        resource.format = None;
+        resource.subresources.clear();
+    }
 }
 
 impl Encoder for OsaCodec {
@@ -118,63 +159,90 @@ impl Encoder for OsaCodec {
 
     fn load_subresources(
         &self,
-        path: &std::path::Path,
+        path: &Path,
         resource: &mut Resource,
-    ) -> Result<(), crate::error::EncodingError> {
-        let mapper: Vec<ResourceItem> = serde_json::from_slice(&resource.data)?;
+        registry: &CodecRegistry,
+    ) -> Result<(), Error> {
         let resource_dirpath = path.join(resource.get_dirname());
+        let mapper = self.read_manifest(resource, &resource_dirpath)?;
 
-        for resource_item in mapper {
-            let resource_path = resource_dirpath.join(resource_item.filename);
-
-            let mut subresource = Resource::load_from(&resource_path)?;
-            codecs::load_subresources(&resource_dirpath, &mut subresource)?;
+        for resource_item in &mapper {
+            let subresource = Self::load_subresource(&resource_dirpath, resource_item, registry)?;
             resource.subresources.push(subresource);
         }
+        // Let encode() see the files added to the manifest
+        resource.data = serde_json::to_vec_pretty(&mapper)?;
 
         Ok(())
     }
 
-    fn encode(&self, resource: &mut Resource) -> Result<(), crate::error::EncodingError> {
+    fn encode(&self, resource: &mut Resource, registry: &CodecRegistry) -> Result<(), Error> {
         // first encode all subresources
-        for subresource in resource.subresources.iter_mut() {
-            codecs::encode(subresource)?;
-        }
+        resource
+            .subresources
+            .par_iter_mut()
+            .try_for_each(|subresource| {
+                let identifier = subresource.identifier;
+                registry
+                    .encode(subresource)
+                    .map_err(|e| e.with_resource(identifier))
+            })?;
 
-        let mut data: Vec<u8> = vec![];
         let mapper: Vec<ResourceItem> = serde_json::from_slice(&resource.data)?;
+        let entries: Vec<&Resource> = mapper
+            .iter()
+            .filter_map(|mapper_entry| {
+                resource
+                    .subresources
+                    .iter()
+                    .find(|r| r.identifier == mapper_entry.identifier)
+            })
+            .collect();
+
+        let mut writer = OsaWriter::new(Cursor::new(vec![]), entries.len() as u32)?;
+        for subresource in entries {
+            writer.write_entry(subresource.identifier, &subresource.data, false)?;
+        }
+        writer.finish()?;
+
+        resource.data = writer.into_inner().into_inner();
+        Self::finish_encoding(resource);
 
-        // Header
-        data.extend_from_slice(&[0x41, 0x55, 0x46, 0x53]);
-        data.extend_from_slice(&u32::to_le_bytes(resource.subresources.len() as u32));
-
-        let directory_offset = (8 + resource.subresources.len() * 12) as u32;
-
-        let mut subresources_data: Vec<u8> = vec![];
-        for mapper_entry in mapper {
-            if let Some(subresource) = resource
-                .subresources
-                .iter()
-                .find(|r| r.identifier == mapper_entry.identifier)
-            {
-                let subresource_data = subresource.data.clone();
-
-                // Directory entry
-                data.extend_from_slice(&u32::to_le_bytes(subresource.identifier));
-                data.extend_from_slice(&u32::to_le_bytes(
-                    directory_offset + subresources_data.len() as u32,
-                ));
-                data.extend_from_slice(&u32::to_le_bytes(subresource.data.len() as u32));
-
-                subresources_data.extend(subresource_data);
+        Ok(())
+    }
+
+    fn encode_into(
+        &self,
+        path: &Path,
+        resource: &mut Resource,
+        registry: &CodecRegistry,
+        sink: &mut dyn WriteSeek,
+    ) -> Result<(), Error> {
+        let resource_dirpath = path.join(resource.get_dirname());
+        let mapper = self.read_manifest(resource, &resource_dirpath)?;
+
+        let mut writer = OsaWriter::new(sink, mapper.len() as u32)?;
+        // Only keep as many entries in memory as can be worked on in parallel
+        for chunk in mapper.chunks(rayon::current_num_threads()) {
+            let subresources = chunk
+                .par_iter()
+                .map(|mapper_entry| {
+                    let mut subresource =
+                        Self::load_subresource(&resource_dirpath, mapper_entry, registry)?;
+                    registry
+                        .encode(&mut subresource)
+                        .map_err(|e| e.with_resource(mapper_entry.identifier))?;
+                    Ok(subresource)
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            for subresource in subresources {
+                writer.write_entry(subresource.identifier, &subresource.data, false)?;
             }
         }
+        writer.finish()?;
 
-        data.extend(subresources_data);
-        resource.data = data;
-        resource.extension = Some("osa".to_string());
-        resource.format = None;
-        resource.subresources.clear();
+        resource.data.clear();
+        Self::finish_encoding(resource);
 
         Ok(())
     }
@@ -1,9 +1,10 @@
 use std::fmt::Display;
 
-use super::{Decoder, Encoder};
-use crate::error::DecodingError;
+use super::{CodecRegistry, Decoder, Encoder};
+use crate::error::Error;
 use crate::resource::Resource;
 
+/// Fallback for resources no other codec recognizes.
 #[derive(Debug)]
 pub struct RawCodec;
 
@@ -18,7 +19,7 @@ impl Decoder for RawCodec {
         10
     }
 
-    fn decode(&self, resource: &mut Resource) -> Result<(), DecodingError> {
+    fn decode(&self, resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         resource.extension = Some("raw".to_string());
         resource.hidden = true;
 
@@ -35,11 +36,12 @@ impl Encoder for RawCodec {
         &self,
         _path: &std::path::Path,
         _resource: &mut Resource,
-    ) -> Result<(), crate::error::EncodingError> {
+        _registry: &CodecRegistry,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
-    fn encode(&self, _resource: &mut Resource) -> Result<(), crate::error::EncodingError> {
+    fn encode(&self, _resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         Ok(())
     }
 }
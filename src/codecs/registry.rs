@@ -0,0 +1,173 @@
+use std::path::Path;
+
+use log::debug;
+
+use super::{
+    Codec, OggCodec, OldEngineCodec, OsaCodec, RawCodec, SaveCodec, SwordTextCodec, TtfCodec,
+    TxtCodec, Vt7aCodec, WebmCodec, WebpCodec, WriteSeek, XmlCodec,
+};
+use crate::error::Error;
+use crate::resource::Resource;
+
+/// Ordered list of codecs used to decode and encode resources.
+///
+/// For every resource the codec with the highest match score is chosen. If
+/// several codecs report the same score, the one earlier in the list wins.
+/// [`CodecRegistry::default`] contains all built-in codecs.
+pub struct CodecRegistry {
+    codecs: Vec<Box<dyn Codec>>,
+}
+
+impl Default for CodecRegistry {
+    fn default() -> Self {
+        Self {
+            codecs: vec![
+                Box::new(OldEngineCodec {}),
+                Box::new(OggCodec {}),
+                Box::new(OsaCodec::default()),
+                Box::new(RawCodec {}),
+                Box::new(SaveCodec {}),
+                Box::new(SwordTextCodec::default()),
+                Box::new(TtfCodec {}),
+                Box::new(TxtCodec {}),
+                Box::new(Vt7aCodec::default()),
+                Box::new(WebmCodec {}),
+                Box::new(WebpCodec {}),
+                Box::new(XmlCodec {}),
+            ],
+        }
+    }
+}
+
+impl CodecRegistry {
+    /// Creates a registry without any codecs.
+    pub fn empty() -> Self {
+        Self { codecs: vec![] }
+    }
+
+    /// Adds a codec in front of all others.
+    ///
+    /// If a codec with the same name (its `Display` output) is already
+    /// registered, it is replaced and keeps its position.
+    pub fn register(&mut self, codec: Box<dyn Codec>) {
+        let name = codec.to_string();
+        match self.position(&name) {
+            Some(index) => self.codecs[index] = codec,
+            None => self.codecs.insert(0, codec),
+        }
+    }
+
+    /// Removes the codec with the given name and returns it.
+    pub fn unregister(&mut self, name: &str) -> Option<Box<dyn Codec>> {
+        self.position(name).map(|index| self.codecs.remove(index))
+    }
+
+    /// Moves the codec with the given name in front of all others, so it wins
+    /// ties against them. Returns false if no such codec is registered.
+    pub fn prioritize(&mut self, name: &str) -> bool {
+        match self.position(name) {
+            Some(index) => {
+                let codec = self.codecs.remove(index);
+                self.codecs.insert(0, codec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns all codecs in order of priority.
+    pub fn codecs(&self) -> impl Iterator<Item = &dyn Codec> {
+        self.codecs.iter().map(|c| c.as_ref())
+    }
+
+    /// Returns the codec which would decode the resource.
+    pub fn find_decoder(&self, resource: &Resource) -> Option<&dyn Codec> {
+        let mut best_match = 0;
+        let mut best_decoder = self.codecs.last()?;
+
+        for decoder in &self.codecs {
+            let current_match = decoder.matches_decoder(resource);
+            if current_match > best_match {
+                best_match = current_match;
+                best_decoder = decoder;
+            }
+        }
+
+        Some(best_decoder.as_ref())
+    }
+
+    /// Returns the codec which would encode the resource.
+    pub fn find_encoder(&self, resource: &Resource) -> Option<&dyn Codec> {
+        let mut best_match = 0;
+        let mut best_encoder = self.codecs.last()?;
+
+        for encoder in &self.codecs {
+            let current_match = encoder.matches_encoder(resource);
+            if current_match > best_match {
+                best_match = current_match;
+                best_encoder = encoder;
+            }
+        }
+
+        Some(best_encoder.as_ref())
+    }
+
+    /// Decodes a resource with the best matching codec.
+    pub fn decode(&self, resource: &mut Resource) -> Result<(), Error> {
+        let decoder = self
+            .find_decoder(resource)
+            .ok_or_else(|| Error::parsing(format!("No codec registered to decode {}", resource)))?;
+
+        debug!("{}: {}", decoder, resource);
+        decoder
+            .decode(resource, self)
+            .map_err(|e| e.with_codec(decoder))
+    }
+
+    /// Loads the subresources of a decoded resource from `path` with the best
+    /// matching codec.
+    pub fn load_subresources(&self, path: &Path, resource: &mut Resource) -> Result<(), Error> {
+        let encoder = self
+            .find_encoder(resource)
+            .ok_or_else(|| Error::parsing(format!("No codec registered to encode {}", resource)))?;
+
+        debug!("{}: {}", encoder, resource);
+        encoder
+            .load_subresources(path, resource, self)
+            .map_err(|e| e.with_codec(encoder))
+    }
+
+    /// Encodes a resource with the best matching codec.
+    pub fn encode(&self, resource: &mut Resource) -> Result<(), Error> {
+        let encoder = self
+            .find_encoder(resource)
+            .ok_or_else(|| Error::parsing(format!("No codec registered to encode {}", resource)))?;
+
+        debug!("{}: {}", encoder, resource);
+        encoder
+            .encode(resource, self)
+            .map_err(|e| e.with_codec(encoder))
+    }
+
+    /// Loads the subresources of a decoded resource from `path`, encodes it
+    /// with the best matching codec and writes the result into `sink`.
+    pub fn encode_into(
+        &self,
+        path: &Path,
+        resource: &mut Resource,
+        sink: &mut dyn WriteSeek,
+    ) -> Result<(), Error> {
+        let encoder = self
+            .find_encoder(resource)
+            .ok_or_else(|| Error::parsing(format!("No codec registered to encode {}", resource)))?;
+
+        debug!("{}: {}", encoder, resource);
+        encoder
+            .encode_into(path, resource, self, sink)
+            .map_err(|e| e.with_codec(encoder))
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.codecs.iter().position(|c| c.to_string() == name)
+    }
+}
@@ -4,8 +4,8 @@ use std::io::{Cursor, Read};
 
 use serde::{Deserialize, Serialize};
 
-use super::{Decoder, Encoder};
-use crate::error::DecodingError;
+use super::{CodecRegistry, Decoder, Encoder};
+use crate::error::Error;
 use crate::resource::Resource;
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -17,6 +17,27 @@ pub struct SaveData {
     savetime: u64,
 }
 
+impl SaveData {
+    /// Returns every value of the savegame with a name, script variables by
+    /// their own name and the others like `sections[3]`.
+    pub(crate) fn variables(&self) -> Vec<(String, serde_json::Value)> {
+        let mut variables = vec![];
+        for (index, value) in self.sections_data.iter().enumerate() {
+            variables.push((format!("sections[{}]", index), (*value).into()));
+        }
+        for (name, value) in &self.script_vars_data {
+            variables.push((name.clone(), (*value).into()));
+        }
+        for (index, value) in self.player_data.iter().enumerate() {
+            variables.push((format!("player[{}]", index), (*value).into()));
+        }
+        variables.push(("playtime".to_string(), self.playtime.into()));
+        variables.push(("savetime".to_string(), self.savetime.into()));
+        variables
+    }
+}
+
+/// Converts savegames into JSON and back.
 #[derive(Debug)]
 pub struct SaveCodec;
 
@@ -34,7 +55,7 @@ impl Decoder for SaveCodec {
         0
     }
 
-    fn decode(&self, resource: &mut Resource) -> Result<(), DecodingError> {
+    fn decode(&self, resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         let mut decoded_data = SaveData::default();
         let mut cursor = Cursor::new(&resource.data);
         let mut u32_buffer: [u8; 4] = [0; 4];
@@ -48,12 +69,12 @@ impl Decoder for SaveCodec {
         }
 
         // read scriptvars
-        for i in 0..AMOUNT_SCRIPT_VARS {
+        for name in SCRIPT_VAR_NAMES {
             cursor.read_exact(&mut u32_buffer)?;
             let value = u32::from_le_bytes(u32_buffer);
             decoded_data
                 .script_vars_data
-                .push((SCRIPT_VAR_NAMES[i].to_string(), value));
+                .push((name.to_string(), value));
         }
 
         // read player
@@ -97,12 +118,13 @@ impl Encoder for SaveCodec {
         &self,
         _path: &std::path::Path,
         _resource: &mut Resource,
-    ) -> Result<(), crate::error::EncodingError> {
+        _registry: &CodecRegistry,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
-    fn encode(&self, resource: &mut Resource) -> Result<(), crate::error::EncodingError> {
-        let decoded_data: SaveData = serde_json::from_slice(&resource.data).unwrap();
+    fn encode(&self, resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
+        let decoded_data: SaveData = serde_json::from_slice(&resource.data)?;
         let mut data: Vec<u8> = vec![];
 
         for value in decoded_data.sections_data {
@@ -4,18 +4,27 @@ use std::io::{BufRead, Cursor, Read};
 
 use serde::{Deserialize, Serialize};
 
-use super::{Decoder, Encoder};
-use crate::error::DecodingError;
+use super::{CodecRegistry, Decoder, Encoder};
+use crate::error::{Error, ErrorKind};
 use crate::resource::Resource;
+use crate::text::detect_language;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A single line of a language file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TextLine {
-    identifier: u32,
-    text: String,
+    /// Identifier the game looks the line up by
+    pub identifier: u32,
+    /// Text of the line, without the terminating null byte
+    pub text: String,
 }
 
-#[derive(Debug)]
-pub struct SwordTextCodec;
+/// Converts language files (from text.vt7a) into JSON and back.
+#[derive(Debug, Default)]
+pub struct SwordTextCodec {
+    /// Add the language of decoded files to their filename, e.g.
+    /// `0000000b.german.sword_text.json`
+    pub label_files: bool,
+}
 
 impl Display for SwordTextCodec {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -31,7 +40,7 @@ impl Decoder for SwordTextCodec {
         0
     }
 
-    fn decode(&self, resource: &mut Resource) -> Result<(), DecodingError> {
+    fn decode(&self, resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         let mut lines: Vec<TextLine> = vec![];
 
         let mut directory_cursor = Cursor::new(&resource.data);
@@ -42,24 +51,41 @@ impl Decoder for SwordTextCodec {
         directory_cursor.set_position(8);
 
         // Extract amount of lines
-        directory_cursor.read_exact(&mut field_buffer)?;
+        directory_cursor
+            .read_exact(&mut field_buffer)
+            .map_err(|e| Error::from(e).at_offset(8))?;
         let number_of_lines = u32::from_le_bytes(field_buffer);
 
         for _ in 0..number_of_lines {
-            directory_cursor.read_exact(&mut field_buffer)?;
+            let directory_position = directory_cursor.position();
+            directory_cursor
+                .read_exact(&mut field_buffer)
+                .map_err(|e| Error::from(e).at_offset(directory_position))?;
             let identifier = u32::from_le_bytes(field_buffer);
 
-            directory_cursor.read_exact(&mut field_buffer)?;
+            directory_cursor
+                .read_exact(&mut field_buffer)
+                .map_err(|e| Error::from(e).at_offset(directory_position))?;
             let offset = u32::from_le_bytes(field_buffer);
 
+            if offset as usize >= resource.data.len() {
+                return Err(Error::new(ErrorKind::OffsetOutOfRange {
+                    offset: offset as u64,
+                    length: 1,
+                    size: resource.data.len() as u64,
+                })
+                .at_offset(directory_position));
+            }
+
             let mut string_buffer = Vec::new();
             text_cursor.set_position(offset as u64);
             text_cursor.read_until(b'\0', &mut string_buffer)?;
             let text = str::from_utf8(&string_buffer).map_err(|e| {
-                DecodingError::ParsingError(format!(
-                    "Parsing text error with identifier {:08x} at offset 0x{:08x}: {}",
-                    identifier, offset, e
+                Error::parsing(format!(
+                    "Parsing text error with identifier {:08x}: {}",
+                    identifier, e
                 ))
+                .at_offset(offset as u64)
             })?;
 
             lines.push(TextLine {
@@ -68,6 +94,9 @@ impl Decoder for SwordTextCodec {
             });
         }
 
+        if self.label_files {
+            resource.label = detect_language(&lines).map(|language| language.name().to_string());
+        }
         let serialized_lines = serde_json::to_string_pretty(&lines).unwrap();
         resource.data = serialized_lines.as_bytes().to_vec();
         resource.extension = Some("json".to_string());
@@ -91,11 +120,12 @@ impl Encoder for SwordTextCodec {
         &self,
         _path: &std::path::Path,
         _resource: &mut Resource,
-    ) -> Result<(), crate::error::EncodingError> {
+        _registry: &CodecRegistry,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
-    fn encode(&self, resource: &mut Resource) -> Result<(), crate::error::EncodingError> {
+    fn encode(&self, resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         let lines: Vec<TextLine> = serde_json::from_slice(&resource.data)?;
         let mut data: Vec<u8> = vec![];
 
@@ -124,6 +154,7 @@ impl Encoder for SwordTextCodec {
         resource.data = data;
         resource.extension = Some("swordtext".to_string());
         resource.format = None;
+        resource.label = None;
         resource.subresources.clear();
 
         Ok(())
@@ -1,10 +1,11 @@
 use core::str;
 use std::fmt::Display;
 
-use super::{Decoder, Encoder};
-use crate::error::DecodingError;
+use super::{CodecRegistry, Decoder, Encoder};
+use crate::error::Error;
 use crate::resource::Resource;
 
+/// Recognizes UTF-8 text files.
 #[derive(Debug)]
 pub struct TxtCodec;
 
@@ -22,7 +23,7 @@ impl Decoder for TxtCodec {
         }
     }
 
-    fn decode(&self, resource: &mut Resource) -> Result<(), DecodingError> {
+    fn decode(&self, resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         resource.extension = Some("txt".to_string());
         Ok(())
     }
@@ -40,11 +41,12 @@ impl Encoder for TxtCodec {
         &self,
         _path: &std::path::Path,
         _resource: &mut Resource,
-    ) -> Result<(), crate::error::EncodingError> {
+        _registry: &CodecRegistry,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
-    fn encode(&self, _resource: &mut Resource) -> Result<(), crate::error::EncodingError> {
+    fn encode(&self, _resource: &mut Resource, _registry: &CodecRegistry) -> Result<(), Error> {
         Ok(())
     }
 }
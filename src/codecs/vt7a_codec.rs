@@ -1,37 +1,30 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
-use std::io::{self, Cursor, Read};
-
-use log::trace;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_with::base64::Base64;
-use serde_with::serde_as;
-
-use super::{Decoder, Encoder};
-use crate::codecs;
-use crate::compression::{compress, decompress, CompressionFormat};
-use crate::error::{DecodingError, EncodingError};
+use serde_with::{serde_as, DisplayFromStr};
+use sha2::{Digest, Sha256};
+
+use super::{find_new_files, CodecRegistry, Decoder, Encoder, WriteSeek};
+use crate::archive::{
+    compress_entry, compress_entry_with_level, size_field, ArchiveEntry, ArchiveReader,
+    ArchiveWriter, Vt7aArchive, Vt7aWriter,
+};
+use crate::compression::{
+    candidate_levels, decompress, default_level, find_level, CompressionFormat, CompressionSpec,
+};
+use crate::error::Error;
 use crate::resource::Resource;
 
-#[derive(Debug, Clone, Copy)]
-pub enum Vt7aVersion {
-    Two,
-    Three,
-}
-
-impl TryFrom<u32> for Vt7aVersion {
-    type Error = DecodingError;
-
-    fn try_from(value: u32) -> Result<Self, Self::Error> {
-        match value {
-            2 => Ok(Self::Two),
-            3 => Ok(Self::Three),
-            _ => Err(DecodingError::ParsingError(format!(
-                "Archive header has wrong version number {}",
-                value
-            ))),
-        }
-    }
-}
+pub use crate::archive::Vt7aVersion;
 
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize)]
@@ -41,10 +34,312 @@ struct ResourceItem {
     filename: String,
     #[serde_as(as = "Option<Base64>")]
     original_data: Option<Vec<u8>>,
+    /// Offset of the data in the original archive, which decides the order
+    /// in which the data is written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    offset: Option<u32>,
+    /// Bytes following the data in the original archive
+    #[serde_as(as = "Option<Base64>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    padding: Option<Vec<u8>>,
+    /// Compression requested for this entry, replacing `compressed`
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression: Option<CompressionSpec>,
+    /// Compression level which reproduces the original compressed data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression_level: Option<i32>,
+    /// SHA-256 of the uncompressed data in the original archive, which tells
+    /// whether the file was changed since decoding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    /// Compressed data from the original archive, used as long as the data
+    /// still matches `sha256`
+    #[serde_as(as = "Option<Base64>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    original_compressed: Option<Vec<u8>>,
+}
+
+impl ResourceItem {
+    fn new(identifier: u32, compressed: bool, filename: String) -> Self {
+        Self {
+            identifier,
+            compressed,
+            filename,
+            original_data: None,
+            offset: None,
+            padding: None,
+            compression: None,
+            compression_level: None,
+            sha256: None,
+            original_compressed: None,
+        }
+    }
+}
+
+/// Archive file a manifest was decoded from.
+#[derive(Debug, Serialize, Deserialize)]
+struct SourceArchive {
+    path: PathBuf,
+    /// Size of the file when it was decoded
+    size: u64,
+    /// SHA-256 hash of the file when it was decoded. Older manifests without
+    /// it cannot verify the file and recompress all entries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+/// Listing of a decoded archive, with everything needed to reproduce the
+/// original archive byte for byte.
+///
+/// Older versions only wrote the list of entries, which is still accepted.
+#[serde_as]
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Third field of the archive header, most likely the time of creation.
+    /// Older manifests kept it as identifier of the archive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u32>,
+    /// Archive whose compressed data is copied for unchanged entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<SourceArchive>,
+    /// Bytes between the directory and the first entry in the original archive
+    #[serde_as(as = "Option<Base64>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    leading_data: Option<Vec<u8>>,
+    /// Compression requested by format or extension of the files, e.g.
+    /// `"webm": "none"`, for entries without their own `compression`
+    #[serde_as(as = "BTreeMap<_, DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    compression: BTreeMap<String, CompressionSpec>,
+    entries: Vec<ResourceItem>,
+}
+
+impl Manifest {
+    fn from_slice(data: &[u8]) -> Result<Self, Error> {
+        let is_listing = data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'[');
+        if is_listing {
+            return Ok(Self {
+                entries: serde_json::from_slice(data)?,
+                ..Default::default()
+            });
+        }
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    /// Returns the indices of the entries in the order their data is written:
+    /// by original offset, followed by the entries without one.
+    fn data_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.entries.len()).collect();
+        order.sort_by_key(|&i| {
+            let offset = self.entries[i].offset;
+            (offset.is_none(), offset)
+        });
+        order
+    }
 }
 
+/// Source archive of a manifest, opened to copy the compressed data of
+/// unchanged entries.
 #[derive(Debug)]
-pub struct Vt7aCodec;
+struct SourceReader {
+    path: PathBuf,
+    /// Directory of the archive by identifier and offset
+    entries: HashMap<(u32, u32), ArchiveEntry>,
+    archive: Mutex<Vt7aArchive<BufReader<File>>>,
+}
+
+impl SourceReader {
+    /// Opens the source archive. Returns `None` and warns if it is gone or
+    /// changed since decoding, as the unchanged entries are then recompressed.
+    fn open(source: &SourceArchive) -> Option<Self> {
+        let archive = File::open(&source.path)
+            .map_err(Error::from)
+            .and_then(|file| {
+                let size = file.metadata()?.len();
+                if size != source.size {
+                    return Err(Error::manifest_mismatch(format!(
+                        "the file has {} bytes instead of {}",
+                        size, source.size
+                    )));
+                }
+                let Some(expected) = &source.sha256 else {
+                    return Err(Error::manifest_mismatch(
+                        "the manifest has no SHA-256 hash of the file",
+                    ));
+                };
+                let mut reader = BufReader::new(file);
+                let mut hasher = Sha256::new();
+                io::copy(&mut reader, &mut hasher)?;
+                let sha256 = hex(&hasher.finalize());
+                if sha256 != *expected {
+                    return Err(Error::manifest_mismatch(format!(
+                        "the file has SHA-256 {} instead of {}",
+                        sha256, expected
+                    )));
+                }
+                reader.seek(SeekFrom::Start(0))?;
+                Vt7aArchive::new(reader)
+            });
+        match archive {
+            Ok(archive) => {
+                let mut entries = HashMap::new();
+                for entry in archive.entries() {
+                    entries
+                        .entry((entry.identifier, entry.offset))
+                        .or_insert(*entry);
+                }
+                Some(Self {
+                    path: source.path.clone(),
+                    entries,
+                    archive: Mutex::new(archive),
+                })
+            }
+            Err(err) => {
+                log::warn!(
+                    "Cannot use source archive {}, compressing unchanged entries again: {}",
+                    source.path.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Reads the compressed data of the entry listed by `resource_item`, if
+    /// the source archive still has it at the same place and with the same
+    /// size and compression.
+    fn read_entry(
+        &self,
+        resource_item: &ResourceItem,
+        size: usize,
+        compression: CompressionFormat,
+    ) -> Result<Option<(ArchiveEntry, Vec<u8>)>, Error> {
+        let Some(entry) = resource_item
+            .offset
+            .and_then(|offset| self.entries.get(&(resource_item.identifier, offset)))
+            .filter(|e| e.size as usize == size && e.compression == compression)
+        else {
+            return Ok(None);
+        };
+        let stored_data = self
+            .archive
+            .lock()
+            .unwrap()
+            .read_raw(entry)
+            .map_err(|e| e.with_path(&self.path))?;
+        Ok(Some((*entry, stored_data)))
+    }
+}
+
+/// Compression levels which reproduce the entries of an archive.
+///
+/// The levels are searched on the first compressed entries, one after the
+/// other, and then tried on all entries. Entries compressed at another level
+/// are not reproduced, but archives created by a different compressor do not
+/// slow down decoding too much, and the result does not depend on the order
+/// in which entries are decoded.
+#[derive(Debug, Default)]
+struct LevelSearch {
+    levels: Vec<i32>,
+}
+
+impl LevelSearch {
+    /// Number of entries the levels are searched on
+    const PROBED_ENTRIES: usize = 8;
+    /// Number of entries matching no level after which the search stops
+    const MAX_MISSES: usize = 4;
+
+    /// Searches the levels on the first compressed entries, given as
+    /// directory entry and stored data.
+    fn probe<'a>(
+        entries: impl IntoIterator<Item = (&'a ArchiveEntry, &'a [u8])>,
+    ) -> Result<Self, Error> {
+        let mut levels: Vec<i32> = vec![];
+        let mut misses = 0;
+        let compressed = entries
+            .into_iter()
+            .filter(|(entry, _)| entry.is_compressed());
+        for (entry, stored_data) in compressed.take(Self::PROBED_ENTRIES) {
+            let data = decompress(stored_data, entry.compression)
+                .map_err(|e| e.with_resource(entry.identifier))?;
+            let known = find_level(&data, stored_data, entry.compression, levels.clone());
+            if known.is_some() {
+                continue;
+            }
+            let candidates = candidate_levels(entry.compression)
+                .into_iter()
+                .filter(|level| !levels.contains(level));
+            match find_level(&data, stored_data, entry.compression, candidates) {
+                Some(level) => levels.push(level),
+                None => misses += 1,
+            }
+            if misses >= Self::MAX_MISSES {
+                break;
+            }
+        }
+        Ok(Self { levels })
+    }
+
+    fn find(&self, data: &[u8], compressed: &[u8], format: CompressionFormat) -> Option<i32> {
+        find_level(data, compressed, format, self.levels.iter().copied())
+    }
+}
+
+/// Third header field written by [`Vt7aCodec`] when encoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Vt7aTimestamp {
+    /// Keep the value of the original archive
+    #[default]
+    Preserve,
+    /// Use the current time
+    Now,
+    /// Use the given value
+    Fixed(u32),
+}
+
+impl FromStr for Vt7aTimestamp {
+    type Err = Error;
+
+    /// Parses `preserve`, `now` or a decimal or hexadecimal (`0x...`) value.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = match s.strip_prefix("0x") {
+            Some(digits) => u32::from_str_radix(digits, 16),
+            None => s.parse(),
+        };
+        match (s, value) {
+            ("preserve", _) => Ok(Self::Preserve),
+            ("now", _) => Ok(Self::Now),
+            (_, Ok(value)) => Ok(Self::Fixed(value)),
+            (_, Err(_)) => Err(Error::parsing(format!(
+                "'{}' is not a timestamp, expected preserve, now or a number",
+                s
+            ))),
+        }
+    }
+}
+
+/// Unpacks and packs VT7A archives (.vt7a).
+#[derive(Debug, Default)]
+pub struct Vt7aCodec {
+    /// Also pack files from the folder of a decoded archive which are not
+    /// listed in its manifest, instead of only reporting them
+    pub include_new_files: bool,
+    /// File being decoded. The manifest references it, so encoding can copy
+    /// the compressed data of unchanged entries instead of embedding it.
+    pub source_archive: Option<PathBuf>,
+    /// Compression overriding the manifests, by identifier (8 hex digits) or
+    /// by format or extension of the files
+    pub compression: BTreeMap<String, CompressionSpec>,
+    /// Third header field of the encoded archives
+    pub timestamp: Vt7aTimestamp,
+    /// Search the compression level of every compressed entry, so encoding
+    /// can reproduce it. This compresses every entry again at several levels,
+    /// which is only worth it when the decoded archive is saved. Otherwise the
+    /// compressed data is kept in the manifest, unless the source archive is
+    /// referenced.
+    pub find_compression_levels: bool,
+}
 
 impl Display for Vt7aCodec {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -60,58 +355,74 @@ impl Decoder for Vt7aCodec {
         0
     }
 
-    fn decode(&self, resource: &mut Resource) -> Result<(), DecodingError> {
-        let mut resources: Vec<Resource> = vec![];
-        let mut resource_items: Vec<ResourceItem> = vec![];
-        let mut cursor = Cursor::new(&resource.data);
-        let mut buffer: [u8; 4] = [0; 4];
-
-        cursor.set_position(0);
+    fn decode(&self, resource: &mut Resource, registry: &CodecRegistry) -> Result<(), Error> {
+        let mut archive = Vt7aArchive::new(Cursor::new(&resource.data))?;
+        let archive_version = archive.version();
+        let timestamp = archive.timestamp();
+        let (leading_data, paddings) = Self::read_layout(&resource.data, archive.entries());
+        let source = self.source_reference(&resource.data, &archive);
+
+        // Extract files
+        let entries = archive
+            .entries()
+            .to_vec()
+            .into_iter()
+            .zip(paddings)
+            .map(|(entry, padding)| Ok((entry, padding, archive.read_raw(&entry)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let level_search = match self.find_compression_levels {
+            true => LevelSearch::probe(
+                entries
+                    .iter()
+                    .map(|(entry, _, stored_data)| (entry, stored_data.as_slice())),
+            )?,
+            false => LevelSearch::default(),
+        };
+        let resources = entries
+            .into_par_iter()
+            .map(|(entry, padding, stored_data)| {
+                let mut res = Resource {
+                    identifier: entry.identifier,
+                    data: decompress(&stored_data, entry.compression)
+                        .map_err(|e| e.with_resource(entry.identifier))?,
+                    ..Default::default()
+                };
+
+                let mut item =
+                    ResourceItem::new(entry.identifier, entry.is_compressed(), String::new());
+                item.offset = Some(entry.offset);
+                item.padding = padding;
+                item.sha256 = Some(sha256_hex(&res.data));
+                if entry.is_compressed() {
+                    item.compression_level =
+                        level_search.find(&res.data, &stored_data, entry.compression);
+                    if item.compression_level.is_none() && source.is_none() {
+                        item.original_compressed = Some(stored_data);
+                    }
+                }
 
-        // VT7A header
-        cursor.read_exact(&mut buffer)?;
-        if buffer != [0x56, 0x54, 0x37, 0x41] {
-            return Err(DecodingError::ParsingError(
-                "Archive header is missing VT7A bytes".to_string(),
-            ));
-        }
-        trace!("VT7A header: {:?}", buffer);
-
-        // Version header
-        cursor.read_exact(&mut buffer)?;
-        let archive_version = Vt7aVersion::try_from(u32::from_le_bytes(buffer))?;
-        trace!("VT7A Version: {:?}", archive_version);
-
-        // Unknown1 header
-        cursor.read_exact(&mut buffer)?;
-        resource.identifier = u32::from_le_bytes(buffer);
-        trace!("VT7A Identifier: {:?}", buffer);
-
-        // Number of files header
-        cursor.read_exact(&mut buffer)?;
-        let number_of_files = u32::from_le_bytes(buffer);
-        let mut directory_entry_buffer: [u8; 16] = [0; 16];
-        trace!("VT7A NumOfFiles: {}", number_of_files);
-
-        // Parse directory and extract files
-        for _ in 0..number_of_files {
-            cursor.read_exact(&mut directory_entry_buffer)?;
-            let (mut res, compressed) =
-                Self::decode_single_resource(resource, directory_entry_buffer, archive_version)?;
-            codecs::decode(&mut res)?;
-            resource_items.push(ResourceItem {
-                identifier: res.identifier,
-                compressed,
-                filename: res.get_filename(),
-                original_data: match res.extension.as_deref() {
-                    Some("raw") => Some(res.data.clone()),
-                    _ => None,
-                },
-            });
-            resources.push(res);
-        }
+                registry
+                    .decode(&mut res)
+                    .map_err(|e| e.with_resource(entry.identifier))?;
+                item.filename = res.get_filename();
+                if res.extension.as_deref() == Some("raw") {
+                    item.original_data = Some(res.data.clone());
+                }
+                Ok((res, item))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let (resources, resource_items): (Vec<Resource>, Vec<ResourceItem>) =
+            resources.into_iter().unzip();
+        let manifest = Manifest {
+            timestamp: Some(timestamp),
+            source,
+            leading_data,
+            compression: BTreeMap::new(),
+            entries: resource_items,
+        };
 
-        let serialized_lines = serde_json::to_string_pretty(&resource_items).unwrap();
+        let serialized_lines = serde_json::to_string_pretty(&manifest).unwrap();
         resource.data = serialized_lines.as_bytes().to_vec();
         resource.subresources = resources;
         resource.extension = Some("json".to_string());
@@ -124,55 +435,298 @@ impl Decoder for Vt7aCodec {
 }
 
 impl Vt7aCodec {
-    fn decode_single_resource(
+    /// Returns the third header field kept in the manifest of a decoded
+    /// archive, or `None` for other resources and older manifests.
+    pub fn manifest_timestamp(resource: &Resource) -> Option<u32> {
+        match resource.format.as_deref() {
+            Some("vt7a2" | "vt7a3") => Manifest::from_slice(&resource.data).ok()?.timestamp,
+            _ => None,
+        }
+    }
+
+    /// Returns the third header field for an archive encoded from `manifest`.
+    fn header_timestamp(&self, manifest: &Manifest, resource: &Resource) -> u32 {
+        match self.timestamp {
+            Vt7aTimestamp::Preserve => manifest.timestamp.unwrap_or(resource.identifier),
+            Vt7aTimestamp::Now => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs() as u32)
+                .unwrap_or_default(),
+            Vt7aTimestamp::Fixed(value) => value,
+        }
+    }
+
+    /// Returns the reference to `source_archive` if it is the archive being
+    /// decoded, and not e.g. the outer archive of a nested one.
+    fn source_reference(
+        &self,
+        data: &[u8],
+        archive: &Vt7aArchive<Cursor<&Vec<u8>>>,
+    ) -> Option<SourceArchive> {
+        let path = self.source_archive.as_ref()?;
+        let file = File::open(path).ok()?;
+        let size = file.metadata().ok()?.len();
+        if size != data.len() as u64 {
+            return None;
+        }
+        let source = Vt7aArchive::new(BufReader::new(file)).ok()?;
+        if source.timestamp() != archive.timestamp() || source.entries() != archive.entries() {
+            return None;
+        }
+        Some(SourceArchive {
+            path: fs::canonicalize(path).unwrap_or_else(|_| path.clone()),
+            size,
+            sha256: Some(sha256_hex(data)),
+        })
+    }
+
+    /// Returns the bytes between the directory and the first entry and the
+    /// bytes following each entry, which are not part of any entry.
+    fn read_layout(
+        data: &[u8],
+        entries: &[ArchiveEntry],
+    ) -> (Option<Vec<u8>>, Vec<Option<Vec<u8>>>) {
+        let mut order: Vec<usize> = (0..entries.len()).collect();
+        order.sort_by_key(|&i| entries[i].offset);
+        let start = |i: usize| entries[i].offset as usize;
+        let end = |i: usize| entries[i].offset as usize + entries[i].stored_size() as usize;
+
+        let directory_end = 16 + 16 * entries.len();
+        let first_offset = order.first().map(|&i| start(i)).unwrap_or(data.len());
+        let leading_data = (first_offset > directory_end && first_offset <= data.len())
+            .then(|| data[directory_end..first_offset].to_vec());
+
+        let mut paddings = vec![None; entries.len()];
+        for (position, &i) in order.iter().enumerate() {
+            let next_offset = order
+                .get(position + 1)
+                .map(|&j| start(j))
+                .unwrap_or(data.len());
+            // Overlapping entries cannot be reproduced, they are written one
+            // after the other instead
+            if next_offset > end(i) && next_offset <= data.len() {
+                paddings[i] = Some(data[end(i)..next_offset].to_vec());
+            }
+        }
+        (leading_data, paddings)
+    }
+
+    /// Reads the manifest of a decoded archive and adds the new files from its
+    /// folder if requested.
+    fn read_manifest(
+        &self,
         resource: &Resource,
-        directory_entry: [u8; 16],
+        resource_dirpath: &Path,
+    ) -> Result<Manifest, Error> {
+        let mut manifest = Manifest::from_slice(&resource.data)?;
+        let listed: Vec<(u32, &str)> = manifest
+            .entries
+            .iter()
+            .map(|item| (item.identifier, item.filename.as_str()))
+            .collect();
+
+        for (identifier, filename) in find_new_files(resource_dirpath, &listed)? {
+            if self.include_new_files {
+                log::info!(
+                    "Adding new file {}",
+                    resource_dirpath.join(&filename).display()
+                );
+                manifest
+                    .entries
+                    .push(ResourceItem::new(identifier, true, filename));
+            } else {
+                log::warn!(
+                    "Skipping {}, it is not listed in the manifest",
+                    resource_dirpath.join(&filename).display()
+                );
+            }
+        }
+        Ok(manifest)
+    }
+
+    fn archive_version(resource: &Resource) -> Result<Vt7aVersion, Error> {
+        match resource.format.as_deref() {
+            Some("vt7a") | Some("vt7a2") => Ok(Vt7aVersion::Two),
+            Some("vt7a3") => Ok(Vt7aVersion::Three),
+            _ => Err(Error::parsing(format!(
+                "wrong archive format {:?}",
+                resource.format
+            ))),
+        }
+    }
+
+    fn load_subresource(
+        resource_dirpath: &Path,
+        resource_item: &ResourceItem,
+        registry: &CodecRegistry,
+    ) -> Result<Resource, Error> {
+        let resource_path = resource_dirpath.join(&resource_item.filename);
+
+        let mut subresource = match Resource::load_from(&resource_path) {
+            Ok(v) => v,
+            Err(_) => {
+                let mut r = Resource::default();
+                r.parse_filename(&resource_item.filename.clone())?;
+                r.data = match &resource_item.original_data {
+                    Some(d) => d.clone(),
+                    None => {
+                        return Err(Error::manifest_mismatch(format!(
+                            "File {} not found",
+                            &resource_item.filename
+                        ))
+                        .with_resource(resource_item.identifier))
+                    }
+                };
+                r
+            }
+        };
+        if subresource.identifier != resource_item.identifier {
+            return Err(Error::manifest_mismatch(format!(
+                "File {} does not belong to identifier {:08x}",
+                &resource_item.filename, resource_item.identifier
+            )));
+        }
+        registry
+            .load_subresources(resource_dirpath, &mut subresource)
+            .map_err(|e| e.with_resource(resource_item.identifier))?;
+        Ok(subresource)
+    }
+
+    /// Returns the compression requested for an entry, in order of
+    /// precedence: by the codec for its identifier, by the codec for its format
+    /// or extension, by the entry itself and by the manifest for its format or
+    /// extension.
+    fn compression_spec(
+        &self,
+        manifest: &Manifest,
+        resource_item: &ResourceItem,
+    ) -> Option<CompressionSpec> {
+        let mut tagged = Resource::default();
+        // Entries without tags are only selected by their identifier
+        let _ = tagged.parse_filename(&resource_item.filename);
+        let tags = [tagged.format, tagged.extension];
+        let by_tag = |specs: &BTreeMap<String, CompressionSpec>| {
+            tags.iter()
+                .flatten()
+                .find_map(|tag| specs.get(tag))
+                .copied()
+        };
+
+        self.compression
+            .get(&format!("{:08x}", resource_item.identifier))
+            .copied()
+            .or_else(|| by_tag(&self.compression))
+            .or(resource_item.compression)
+            .or_else(|| by_tag(&manifest.compression))
+    }
+
+    fn compress_subresource(
+        subresource: &Resource,
+        resource_item: &ResourceItem,
         archive_version: Vt7aVersion,
-    ) -> Result<(Resource, bool), DecodingError> {
-        let mut subresource = Resource::default();
-        let mut resource_cursor = Cursor::new(&resource.data);
-        let mut directory_entry_cursor = Cursor::new(&directory_entry);
-        let mut buffer: [u8; 4] = [0; 4];
-
-        // Identifier
-        directory_entry_cursor.read_exact(&mut buffer)?;
-        let identifier = u32::from_le_bytes(buffer);
-        subresource.identifier = identifier;
-        trace!("-   VT7A File Identifier: {:#x}", identifier);
-
-        // Offset
-        directory_entry_cursor.read_exact(&mut buffer)?;
-        let offset = u32::from_le_bytes(buffer);
-        trace!("    VT7A File Offset: {:#x}", offset);
-
-        // Uncompressed size
-        directory_entry_cursor.read_exact(&mut buffer)?;
-        let size_uncompressed = u32::from_le_bytes(buffer);
-        trace!("    VT7A File Size Uncompressed: {:#x}", size_uncompressed);
-
-        // Compressed size
-        directory_entry_cursor.read_exact(&mut buffer)?;
-        let size_compressed = u32::from_le_bytes(buffer);
-        trace!("    VT7A File Size Compressed: {:#x}", size_compressed);
-
-        // Read data
-        resource_cursor.set_position(offset as u64);
-        let mut size_to_read = size_uncompressed;
-        let mut compression_format = CompressionFormat::None;
-        if size_compressed != 0 {
-            size_to_read = size_compressed;
-            compression_format = match archive_version {
-                Vt7aVersion::Two => CompressionFormat::Zlib,
-                Vt7aVersion::Three => CompressionFormat::Zstd,
-            };
+        spec: Option<CompressionSpec>,
+        source: Option<&SourceReader>,
+    ) -> Result<(ArchiveEntry, Vec<u8>), Error> {
+        let compression_format = archive_version.compression();
+        let original_format = match resource_item.compressed {
+            true => compression_format,
+            false => CompressionFormat::None,
+        };
+        let spec = spec.unwrap_or(CompressionSpec {
+            format: original_format,
+            level: None,
+        });
+        if spec.format != CompressionFormat::None && spec.format != compression_format {
+            return Err(Error::parsing(format!(
+                "Entry {:08x} cannot be compressed with {}, vt7a{} archives use {}",
+                subresource.identifier,
+                spec.format,
+                u32::from(archive_version),
+                compression_format
+            )));
+        }
+        if spec.format == CompressionFormat::None {
+            return compress_entry(
+                subresource.identifier,
+                &subresource.data,
+                CompressionFormat::None,
+            );
+        }
+
+        // Unchanged entries keep their original compressed data, unless a
+        // different compression is requested
+        let keeps_original = spec.format == original_format
+            && spec
+                .level
+                .is_none_or(|level| Some(level) == resource_item.compression_level);
+        let unchanged = keeps_original
+            && resource_item
+                .sha256
+                .as_ref()
+                .is_some_and(|sha256| *sha256 == sha256_hex(&subresource.data));
+        if unchanged {
+            if let Some(source) = source {
+                if let Some(stored) =
+                    source.read_entry(resource_item, subresource.data.len(), compression_format)?
+                {
+                    return Ok(stored);
+                }
+            }
+            if let Some(original_compressed) = &resource_item.original_compressed {
+                let entry = ArchiveEntry {
+                    identifier: subresource.identifier,
+                    offset: 0,
+                    size: size_field(subresource.identifier, subresource.data.len())?,
+                    compressed_size: size_field(subresource.identifier, original_compressed.len())?,
+                    compression: compression_format,
+                };
+                return Ok((entry, original_compressed.clone()));
+            }
+        }
+
+        let original_level = resource_item.compression_level.filter(|_| keeps_original);
+        compress_entry_with_level(
+            subresource.identifier,
+            &subresource.data,
+            compression_format,
+            spec.level
+                .or(original_level)
+                .unwrap_or(default_level(compression_format)),
+        )
+    }
+
+    /// Writes the entries in the order of their original data, each followed
+    /// by its padding. `compress` returns the stored data of a manifest entry.
+    fn write_entries<W: Write + Seek>(
+        writer: &mut Vt7aWriter<W>,
+        manifest: &Manifest,
+        compress: impl Fn(&ResourceItem) -> Result<(ArchiveEntry, Vec<u8>), Error> + Sync,
+    ) -> Result<(), Error> {
+        if let Some(leading_data) = &manifest.leading_data {
+            writer.write_padding(leading_data)?;
+        }
+
+        // Only keep as many entries in memory as can be worked on in parallel
+        let order = manifest.data_order();
+        for chunk in order.chunks(rayon::current_num_threads()) {
+            let compressed_entries = chunk
+                .par_iter()
+                .map(|&index| compress(&manifest.entries[index]))
+                .collect::<Result<Vec<_>, Error>>()?;
+            for (&index, (entry, stored_data)) in chunk.iter().zip(compressed_entries) {
+                writer.write_raw_entry_at(index, &entry, &stored_data)?;
+                if let Some(padding) = &manifest.entries[index].padding {
+                    writer.write_padding(padding)?;
+                }
+            }
         }
-        let mut data = vec![];
-        resource_cursor
-            .take(size_to_read as u64)
-            .read_to_end(&mut data)?;
-        subresource.data = decompress(&data, compression_format)?;
+        writer.finish()
+    }
 
-        Ok((subresource, size_compressed != 0))
+    fn finish_encoding(resource: &mut Resource) {
+        resource.extension = Some("vt7a".to_string());
+        resource.format = None;
+        resource.subresources.clear();
     }
 }
 
@@ -190,107 +744,158 @@ impl Encoder for Vt7aCodec {
 
     fn load_subresources(
         &self,
-        path: &std::path::Path,
+        path: &Path,
         resource: &mut Resource,
-    ) -> Result<(), crate::error::EncodingError> {
-        let mapper: Vec<ResourceItem> = serde_json::from_slice(&resource.data)?;
+        registry: &CodecRegistry,
+    ) -> Result<(), Error> {
         let resource_dirpath = path.join(resource.get_dirname());
+        let manifest = self.read_manifest(resource, &resource_dirpath)?;
 
-        for resource_item in mapper {
-            let resource_path = resource_dirpath.join(&resource_item.filename);
-
-            let mut subresource = match Resource::load_from(&resource_path) {
-                Ok(v) => v,
-                Err(_) => {
-                    let mut r = Resource::default();
-                    r.parse_filename(&resource_item.filename.clone())?;
-                    r.data = match resource_item.original_data {
-                        Some(d) => d,
-                        None => {
-                            return Err(EncodingError::IOError(io::Error::new(
-                                io::ErrorKind::NotFound,
-                                format!("File {} not found", &resource_item.filename),
-                            )))
-                        }
-                    };
-                    r
-                }
-            };
-            codecs::load_subresources(&resource_dirpath, &mut subresource)?;
+        for resource_item in &manifest.entries {
+            let subresource = Self::load_subresource(&resource_dirpath, resource_item, registry)?;
             resource.subresources.push(subresource);
         }
+        // Let encode() see the files added to the manifest
+        resource.data = serde_json::to_vec_pretty(&manifest)?;
 
         Ok(())
     }
 
-    fn encode(&self, resource: &mut Resource) -> Result<(), crate::error::EncodingError> {
-        let archive_version = match resource.format.as_deref() {
-            Some("vt7a") | Some("vt7a2") => Vt7aVersion::Two,
-            Some("vt7a3") => Vt7aVersion::Three,
-            _ => {
-                return Err(EncodingError::ParsingError(
-                    "wrong archive format".to_string(),
-                ))
-            }
-        };
-
-        for subresource in resource.subresources.iter_mut() {
-            codecs::encode(subresource)?;
+    fn encode(&self, resource: &mut Resource, registry: &CodecRegistry) -> Result<(), Error> {
+        let archive_version = Self::archive_version(resource)?;
+
+        resource
+            .subresources
+            .par_iter_mut()
+            .try_for_each(|subresource| {
+                let identifier = subresource.identifier;
+                registry
+                    .encode(subresource)
+                    .map_err(|e| e.with_resource(identifier))
+            })?;
+
+        let manifest = Manifest::from_slice(&resource.data)?;
+        let mut writer = Vt7aWriter::new(
+            Cursor::new(vec![]),
+            archive_version,
+            self.header_timestamp(&manifest, resource),
+            manifest.entries.len() as u32,
+        )?;
+        let source = manifest.source.as_ref().and_then(SourceReader::open);
+        let mut subresources: HashMap<u32, &Resource> = HashMap::new();
+        for subresource in &resource.subresources {
+            subresources
+                .entry(subresource.identifier)
+                .or_insert(subresource);
         }
+        Self::write_entries(&mut writer, &manifest, |mapper_entry| {
+            let subresource = subresources.get(&mapper_entry.identifier).ok_or_else(|| {
+                Error::manifest_mismatch(format!("File {} was not loaded", &mapper_entry.filename))
+                    .with_resource(mapper_entry.identifier)
+            })?;
+            Self::compress_subresource(
+                subresource,
+                mapper_entry,
+                archive_version,
+                self.compression_spec(&manifest, mapper_entry),
+                source.as_ref(),
+            )
+        })?;
+
+        resource.data = writer.into_inner().into_inner();
+        Self::finish_encoding(resource);
 
-        let mut data: Vec<u8> = vec![];
-        let mapper: Vec<ResourceItem> = serde_json::from_slice(&resource.data)?;
+        Ok(())
+    }
 
-        // Header
-        data.extend_from_slice(&[0x56, 0x54, 0x37, 0x41]);
-        match archive_version {
-            Vt7aVersion::Two => data.extend_from_slice(&[0x02, 0x00, 0x00, 0x00]),
-            Vt7aVersion::Three => data.extend_from_slice(&[0x03, 0x00, 0x00, 0x00]),
-        };
-        data.extend_from_slice(&u32::to_le_bytes(resource.identifier));
-        data.extend_from_slice(&u32::to_le_bytes(resource.subresources.len() as u32));
-
-        let directory_offset = (16 + resource.subresources.len() * 16) as u32;
-
-        let mut subresources_data: Vec<u8> = vec![];
-        for mapper_entry in mapper {
-            if let Some(subresource) = resource
-                .subresources
-                .iter()
-                .find(|r| r.identifier == mapper_entry.identifier)
-            {
-                let mut compression_format = CompressionFormat::None;
-                if mapper_entry.compressed {
-                    compression_format = match archive_version {
-                        Vt7aVersion::Two => CompressionFormat::Zlib,
-                        Vt7aVersion::Three => CompressionFormat::Zstd,
-                    };
-                }
+    fn encode_into(
+        &self,
+        path: &Path,
+        resource: &mut Resource,
+        registry: &CodecRegistry,
+        sink: &mut dyn WriteSeek,
+    ) -> Result<(), Error> {
+        let archive_version = Self::archive_version(resource)?;
+        let resource_dirpath = path.join(resource.get_dirname());
+        let manifest = self.read_manifest(resource, &resource_dirpath)?;
+
+        let mut writer = Vt7aWriter::new(
+            sink,
+            archive_version,
+            self.header_timestamp(&manifest, resource),
+            manifest.entries.len() as u32,
+        )?;
+        let source = manifest.source.as_ref().and_then(SourceReader::open);
+        Self::write_entries(&mut writer, &manifest, |mapper_entry| {
+            let mut subresource =
+                Self::load_subresource(&resource_dirpath, mapper_entry, registry)?;
+            registry
+                .encode(&mut subresource)
+                .map_err(|e| e.with_resource(mapper_entry.identifier))?;
+            Self::compress_subresource(
+                &subresource,
+                mapper_entry,
+                archive_version,
+                self.compression_spec(&manifest, mapper_entry),
+                source.as_ref(),
+            )
+        })?;
+
+        resource.data.clear();
+        Self::finish_encoding(resource);
 
-                let subresource_data = compress(&subresource.data, compression_format)?;
-
-                // Directory entry
-                data.extend_from_slice(&u32::to_le_bytes(subresource.identifier));
-                data.extend_from_slice(&u32::to_le_bytes(
-                    directory_offset + subresources_data.len() as u32,
-                ));
-                data.extend_from_slice(&u32::to_le_bytes(subresource.data.len() as u32));
-                if mapper_entry.compressed {
-                    data.extend_from_slice(&u32::to_le_bytes(subresource_data.len() as u32));
-                } else {
-                    data.extend_from_slice(&u32::to_le_bytes(0));
-                }
+        Ok(())
+    }
+}
 
-                subresources_data.extend(subresource_data);
-            }
-        }
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
 
-        data.extend(subresources_data);
-        resource.data = data;
-        resource.extension = Some("vt7a".to_string());
-        resource.format = None;
-        resource.subresources.clear();
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an archive with leading data, padding between the entries and
+    /// entries stored uncompressed, at the default level and at another one.
+    fn sample_archive(version: Vt7aVersion) -> Vec<u8> {
+        let compression = version.compression();
+        let text = "Nico, I've found the manuscript.\n".repeat(40);
+        let mut writer = Vt7aWriter::new(Cursor::new(vec![]), version, 0x65f99ae3, 4).unwrap();
+        writer.write_padding(b"LEAD").unwrap();
+        writer.write_entry(0x0a, text.as_bytes(), true).unwrap();
+        writer.write_padding(&[0; 3]).unwrap();
+        writer
+            .write_entry(0x0b, b"<config><item name=\"a\"/></config>", false)
+            .unwrap();
+        let (entry, stored) =
+            compress_entry_with_level(0x0c, text.to_uppercase().as_bytes(), compression, 1)
+                .unwrap();
+        writer.write_raw_entry(&entry, &stored).unwrap();
+        writer.write_padding(&[0xff; 5]).unwrap();
+        writer.write_entry(0x0d, &[], true).unwrap();
+        writer.finish().unwrap();
+        writer.into_inner().into_inner()
+    }
+
+    #[test]
+    fn decoding_and_encoding_reproduces_the_archive() {
+        for version in [Vt7aVersion::Two, Vt7aVersion::Three] {
+            let original = sample_archive(version);
+            let mut resource = Resource {
+                data: original.clone(),
+                ..Default::default()
+            };
+            crate::codecs::decode(&mut resource).unwrap();
+            assert_eq!(resource.subresources.len(), 4);
+
+            crate::codecs::encode(&mut resource).unwrap();
+            assert_eq!(sha256_hex(&resource.data), sha256_hex(&original));
+            assert_eq!(resource.data, original);
+        }
     }
 }
@@ -0,0 +1,28 @@
+use std::{fs, io::BufReader, path::Path};
+
+use vt7packer::{
+    archive::{self, Vt7aVersion},
+    Error,
+};
+
+use super::write_output;
+
+pub fn convert_version(
+    filepath: &Path,
+    version: Vt7aVersion,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let filename = filepath.file_name().unwrap().to_string_lossy().to_string();
+    let source = fs::File::open(filepath).map_err(|e| Error::from(e).with_path(filepath))?;
+    let archivepath = write_output(outpath, &filename, |file| {
+        archive::convert_version(BufReader::new(source), file, version)
+            .map_err(|e| e.with_path(filepath))?;
+        Ok(filename.clone())
+    })?;
+    log::info!(
+        "Converted to version {}: {}",
+        u32::from(version),
+        &archivepath.to_string_lossy()
+    );
+    Ok(())
+}
@@ -0,0 +1,369 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use vt7packer::{
+    codecs::CodecRegistry,
+    diff::{diff_resources, ContentDiff, LayoutChange},
+    Error, Resource,
+};
+
+use super::load_decoded;
+
+/// Differences between two directories, with paths relative to them.
+#[derive(Debug, Default, Serialize)]
+struct DirectoryDiff {
+    added: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+    moved: Vec<FileMove>,
+    changed: Vec<FileDiff>,
+}
+
+/// A file whose contents are stored at a new path.
+#[derive(Debug, Serialize)]
+struct FileMove {
+    old: PathBuf,
+    new: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+struct FileDiff {
+    path: PathBuf,
+    diff: ContentDiff,
+}
+
+/// Compares two files or two directories and returns whether they differ.
+pub fn diff(
+    registry: &CodecRegistry,
+    path1: &Path,
+    path2: &Path,
+    json: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if path1.is_dir() && path2.is_dir() {
+        let diff = diff_directories(registry, path1, path2)?;
+        let differs = !(diff.added.is_empty()
+            && diff.removed.is_empty()
+            && diff.moved.is_empty()
+            && diff.changed.is_empty());
+        if json {
+            let mut out = io::stdout().lock();
+            writeln!(out, "{}", serde_json::to_string_pretty(&diff)?)?;
+        } else if !differs {
+            log::info!("The directories have the same contents");
+        } else {
+            let path = |p: &PathBuf| p.display().to_string();
+            log_sections(
+                path1,
+                path2,
+                diff.removed.iter().map(path).collect(),
+                diff.added.iter().map(path).collect(),
+                diff.moved
+                    .iter()
+                    .map(|m| format!("{} -> {}", path(&m.old), path(&m.new)))
+                    .collect(),
+                diff.changed
+                    .iter()
+                    .map(|file| (path(&file.path), &file.diff))
+                    .collect(),
+            );
+        }
+        return Ok(differs);
+    }
+
+    let resource1 = load_decoded(registry, path1)?;
+    let resource2 = load_decoded(registry, path2)?;
+    let diff = diff_resources(&resource1, &resource2);
+
+    if json {
+        let mut out = io::stdout().lock();
+        writeln!(out, "{}", serde_json::to_string_pretty(&diff)?)?;
+        return Ok(diff.is_some());
+    }
+
+    match &diff {
+        None => log::info!("The files have the same contents"),
+        Some(ContentDiff::Archive {
+            added,
+            removed,
+            moved,
+            changed,
+            layout,
+        }) => {
+            log_sections(
+                path1,
+                path2,
+                removed.iter().map(|e| e.filename.clone()).collect(),
+                added.iter().map(|e| e.filename.clone()).collect(),
+                moved
+                    .iter()
+                    .map(|m| format!("{} -> {}", m.old.filename, m.new.filename))
+                    .collect(),
+                changed
+                    .iter()
+                    .map(|e| (e.filename.clone(), &e.diff))
+                    .collect(),
+            );
+            if !layout.is_empty() {
+                log::info!("");
+                log::info!("Header and layout changes:");
+                log_layout(layout, 2);
+            }
+        }
+        Some(diff) => log_changes(diff, 0),
+    }
+    Ok(diff.is_some())
+}
+
+/// Pairs the files of two directories by their relative path and compares
+/// them. Files which only exist on one side with the same contents were moved.
+fn diff_directories(
+    registry: &CodecRegistry,
+    dirpath1: &Path,
+    dirpath2: &Path,
+) -> Result<DirectoryDiff, Error> {
+    let mut files1 = vec![];
+    collect_files(dirpath1, Path::new(""), &mut files1)?;
+    let mut files2 = vec![];
+    collect_files(dirpath2, Path::new(""), &mut files2)?;
+    let lookup1: HashSet<&PathBuf> = files1.iter().collect();
+    let lookup2: HashSet<&PathBuf> = files2.iter().collect();
+
+    let mut diff = DirectoryDiff::default();
+    for file in &files2 {
+        if !lookup1.contains(file) {
+            diff.added.push(file.clone());
+            continue;
+        }
+        log::debug!("Comparing {}", file.display());
+        let resource1 = load_file(registry, dirpath1, file)?;
+        let resource2 = load_file(registry, dirpath2, file)?;
+        if let Some(content) = diff_resources(&resource1, &resource2) {
+            diff.changed.push(FileDiff {
+                path: file.clone(),
+                diff: content,
+            });
+        }
+    }
+    diff.removed = files1
+        .iter()
+        .filter(|file| !lookup2.contains(file))
+        .cloned()
+        .collect();
+
+    let hash = |dirpath: &Path, file: &Path| -> Result<Vec<u8>, Error> {
+        let path = dirpath.join(file);
+        let resource = Resource {
+            data: fs::read(&path).map_err(|e| Error::from(e).with_path(&path))?,
+            ..Default::default()
+        };
+        Ok(resource.hash())
+    };
+    let mut removed_by_hash: HashMap<Vec<u8>, Vec<PathBuf>> = HashMap::new();
+    for file in diff.removed.iter().rev() {
+        removed_by_hash
+            .entry(hash(dirpath1, file)?)
+            .or_default()
+            .push(file.clone());
+    }
+    let mut added = vec![];
+    for file in diff.added {
+        match removed_by_hash
+            .get_mut(&hash(dirpath2, &file)?)
+            .and_then(|files| files.pop())
+        {
+            Some(old) => diff.moved.push(FileMove { old, new: file }),
+            None => added.push(file),
+        }
+    }
+    diff.added = added;
+    let moved: HashSet<&PathBuf> = diff.moved.iter().map(|m| &m.old).collect();
+    diff.removed.retain(|file| !moved.contains(file));
+    Ok(diff)
+}
+
+/// Collects the paths of all files below `dirpath`, relative to it and
+/// sorted. Manifests of decoded archives are left out, as the files in their
+/// folder are compared instead.
+fn collect_files(dirpath: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let path = dirpath.join(relative);
+    let mut names = vec![];
+    for dir_entry in fs::read_dir(&path).map_err(|e| Error::from(e).with_path(&path))? {
+        names.push(dir_entry?.file_name());
+    }
+    names.sort();
+
+    for name in &names {
+        let file = relative.join(name);
+        if dirpath.join(&file).is_dir() {
+            collect_files(dirpath, &file, files)?;
+        } else {
+            let mut folder = name.clone();
+            folder.push(".d");
+            if !names.contains(&folder) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Loads a file for comparison. Files of decoded archives are taken as they
+/// are, all others are decoded.
+fn load_file(registry: &CodecRegistry, dirpath: &Path, file: &Path) -> Result<Resource, Error> {
+    let path = dirpath.join(file);
+    let in_decoded_archive = file
+        .parent()
+        .and_then(|parent| parent.extension())
+        .is_some_and(|extension| extension == "d");
+    let mut resource = Resource::default();
+    let named = file
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| resource.parse_filename(name).is_ok());
+    if (named && in_decoded_archive) || resource.format.is_some() {
+        resource.data = fs::read(&path).map_err(|e| Error::from(e).with_path(&path))?;
+        return Ok(resource);
+    }
+    load_decoded(registry, &path)
+}
+
+/// Logs the differences between two archives or directories in sections.
+fn log_sections(
+    path1: &Path,
+    path2: &Path,
+    removed: Vec<String>,
+    added: Vec<String>,
+    moved: Vec<String>,
+    changed: Vec<(String, &ContentDiff)>,
+) {
+    log::info!("Files only in {}:", path1.display());
+    for name in removed {
+        log::info!("  - {}", name);
+    }
+    log::info!("");
+
+    log::info!("Files only in {}:", path2.display());
+    for name in added {
+        log::info!("  - {}", name);
+    }
+    log::info!("");
+
+    log::info!("Files moved to a new name:");
+    for name in moved {
+        log::info!("  - {}", name);
+    }
+    log::info!("");
+
+    log::info!("Files present in both, but changed:");
+    for (name, diff) in changed {
+        log::info!("  - {}", name);
+        log_changes(diff, 4);
+    }
+}
+
+/// Logs the changes within a file, one per line: `+` for added, `-` for
+/// removed, `>` for moved and `~` for changed contents.
+fn log_changes(diff: &ContentDiff, indent: usize) {
+    let pad = " ".repeat(indent);
+    match diff {
+        ContentDiff::Archive {
+            added,
+            removed,
+            moved,
+            changed,
+            layout,
+        } => {
+            log_layout(layout, indent);
+            for entry in removed {
+                log::info!("{}- {}", pad, entry.filename);
+            }
+            for entry in added {
+                log::info!("{}+ {}", pad, entry.filename);
+            }
+            for entry in moved {
+                log::info!("{}> {} -> {}", pad, entry.old.filename, entry.new.filename);
+            }
+            for entry in changed {
+                log::info!("{}~ {}", pad, entry.filename);
+                log_changes(&entry.diff, indent + 2);
+            }
+        }
+        ContentDiff::Text {
+            added,
+            removed,
+            changed,
+        } => {
+            for line in removed {
+                log::info!("{}- {:08x}: {:?}", pad, line.identifier, line.text);
+            }
+            for line in added {
+                log::info!("{}+ {:08x}: {:?}", pad, line.identifier, line.text);
+            }
+            for line in changed {
+                log::info!(
+                    "{}~ {:08x}: {:?} -> {:?}",
+                    pad,
+                    line.identifier,
+                    line.old,
+                    line.new
+                );
+            }
+        }
+        ContentDiff::Save { changed } => {
+            for variable in changed {
+                log::info!(
+                    "{}~ {}: {} -> {}",
+                    pad,
+                    variable.name,
+                    variable.old,
+                    variable.new
+                );
+            }
+        }
+        ContentDiff::Xml { changed } => {
+            for change in changed {
+                match (&change.old, &change.new) {
+                    (Some(old), Some(new)) => {
+                        log::info!("{}~ {}: {:?} -> {:?}", pad, change.path, old, new)
+                    }
+                    (Some(old), None) => log::info!("{}- {}: {:?}", pad, change.path, old),
+                    (None, Some(new)) => log::info!("{}+ {}: {:?}", pad, change.path, new),
+                    (None, None) => {}
+                }
+            }
+        }
+        ContentDiff::Binary {
+            old_size,
+            new_size,
+            size_delta,
+            first_difference,
+        } => {
+            log::info!(
+                "{}~ size {} -> {} ({:+}), first difference at 0x{:x}",
+                pad,
+                old_size,
+                new_size,
+                size_delta,
+                first_difference
+            );
+        }
+    }
+}
+
+/// Logs the header and layout changes of an archive, one per line.
+fn log_layout(layout: &[LayoutChange], indent: usize) {
+    let pad = " ".repeat(indent);
+    for change in layout {
+        log::info!(
+            "{}~ {}: {} -> {}",
+            pad,
+            change.field,
+            change.old,
+            change.new
+        );
+    }
+}
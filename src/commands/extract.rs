@@ -0,0 +1,220 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::{Path, PathBuf},
+};
+
+use rayon::prelude::*;
+use vt7packer::{
+    archive::{self, ArchiveEntry, ArchiveReader},
+    codecs::{CodecRegistry, RawCodec},
+    compression::decompress,
+    Error, Resource,
+};
+
+/// Identifier of an archive entry, which may contain the wildcards `*` and `?`.
+#[derive(Debug, Clone)]
+struct EntryPattern(String);
+
+impl EntryPattern {
+    fn new(pattern: &str) -> Result<Self, Error> {
+        let pattern = pattern.to_ascii_lowercase();
+        let valid = !pattern.is_empty()
+            && pattern
+                .chars()
+                .all(|c| c.is_ascii_hexdigit() || c == '*' || c == '?')
+            && (pattern.len() <= 8 || pattern.contains('*'));
+        if !valid {
+            return Err(Error::parsing(format!(
+                "'{}' is not an identifier or identifier pattern",
+                pattern
+            )));
+        }
+        Ok(Self(pattern))
+    }
+
+    fn is_wildcard(&self) -> bool {
+        self.0.contains(['*', '?'])
+    }
+
+    fn matches(&self, identifier: u32) -> bool {
+        if self.is_wildcard() {
+            glob_matches(self.0.as_bytes(), format!("{:08x}", identifier).as_bytes())
+        } else {
+            u32::from_str_radix(&self.0, 16) == Ok(identifier)
+        }
+    }
+}
+
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_matches(&pattern[1..], text)
+                || (!text.is_empty() && glob_matches(pattern, &text[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_matches(&pattern[1..], &text[1..]),
+        (Some(p), Some(t)) if p == t => glob_matches(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
+
+/// Splits a path like `sword.vt7a/00012345` into the archive file on disk and
+/// the identifiers following it.
+fn split_archive_path(path: &Path) -> Result<(PathBuf, Vec<String>), Error> {
+    let mut archive_path = path;
+    let mut inner = vec![];
+    while !archive_path.is_file() {
+        match (archive_path.parent(), archive_path.file_name()) {
+            (Some(parent), Some(name)) => {
+                inner.insert(0, name.to_string_lossy().to_string());
+                archive_path = parent;
+            }
+            _ => {
+                return Err(
+                    Error::from(std::io::Error::from(std::io::ErrorKind::NotFound)).with_path(path),
+                )
+            }
+        }
+    }
+    Ok((archive_path.to_path_buf(), inner))
+}
+
+pub fn extract(
+    registry: &CodecRegistry,
+    filepath: &Path,
+    patterns: &[String],
+    outpath: &Path,
+    raw: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (archive_path, inner) = split_archive_path(filepath)?;
+
+    // Without patterns the path itself names the entry to extract
+    let mut selections: Vec<Vec<String>> = patterns
+        .iter()
+        .map(|pattern| {
+            let mut segments = inner.clone();
+            segments.extend(pattern.split('/').map(|s| s.to_string()));
+            segments
+        })
+        .collect();
+    if selections.is_empty() && !inner.is_empty() {
+        selections.push(inner);
+    }
+    if selections.is_empty() {
+        return Err("No identifiers to extract given".into());
+    }
+
+    let raw_registry = {
+        let mut raw_registry = CodecRegistry::empty();
+        raw_registry.register(Box::new(RawCodec {}));
+        raw_registry
+    };
+    let registry = if raw { &raw_registry } else { registry };
+
+    let file = File::open(&archive_path).map_err(|e| Error::from(e).with_path(&archive_path))?;
+    let mut archive =
+        archive::open(BufReader::new(file)).map_err(|e| e.with_path(&archive_path))?;
+
+    let selections = selections
+        .into_iter()
+        .map(|selection| {
+            let segments = selection
+                .iter()
+                .map(|s| EntryPattern::new(s))
+                .collect::<Result<Vec<_>, Error>>()?;
+            Ok((selection, segments))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut unmatched = 0;
+    for (selection, segments) in selections {
+        let extracted = extract_from(registry, archive.as_mut(), &segments, outpath)
+            .map_err(|e| e.with_path(&archive_path))?;
+        if extracted == 0 {
+            log::warn!("No entry matches {}", selection.join("/"));
+            unmatched += 1;
+        }
+    }
+
+    if unmatched > 0 {
+        return Err(format!("{} identifiers did not match any entry", unmatched).into());
+    }
+    Ok(())
+}
+
+/// Extracts the entries matching the first pattern, or descends into them if
+/// more patterns follow. Returns the amount of extracted entries.
+fn extract_from(
+    registry: &CodecRegistry,
+    archive: &mut dyn ArchiveReader,
+    segments: &[EntryPattern],
+    outpath: &Path,
+) -> Result<usize, Error> {
+    let Some((pattern, rest)) = segments.split_first() else {
+        return Ok(0);
+    };
+    let entries: Vec<ArchiveEntry> = archive
+        .entries()
+        .iter()
+        .filter(|entry| pattern.matches(entry.identifier))
+        .copied()
+        .collect();
+
+    if !rest.is_empty() {
+        let mut extracted = 0;
+        for entry in entries {
+            let data = decompress(&archive.read_raw(&entry)?, entry.compression)
+                .map_err(|e| e.with_resource(entry.identifier))?;
+            if !archive::is_archive(&data) {
+                if pattern.is_wildcard() {
+                    continue;
+                }
+                return Err(
+                    Error::parsing("Entry is not an archive").with_resource(entry.identifier)
+                );
+            }
+            let mut nested =
+                archive::open(Cursor::new(data)).map_err(|e| e.with_resource(entry.identifier))?;
+            extracted += extract_from(registry, nested.as_mut(), rest, outpath)
+                .map_err(|e| e.with_resource(entry.identifier))?;
+        }
+        return Ok(extracted);
+    }
+
+    // Only keep as many entries in memory as can be worked on in parallel
+    for chunk in entries.chunks(rayon::current_num_threads()) {
+        let stored_data = chunk
+            .iter()
+            .map(|entry| archive.read_raw(entry))
+            .collect::<Result<Vec<_>, Error>>()?;
+        chunk
+            .par_iter()
+            .zip(stored_data.into_par_iter())
+            .try_for_each(|(entry, data)| {
+                extract_entry(registry, entry, &data, outpath)
+                    .map_err(|e| e.with_resource(entry.identifier))
+            })?;
+    }
+    Ok(entries.len())
+}
+
+fn extract_entry(
+    registry: &CodecRegistry,
+    entry: &ArchiveEntry,
+    stored_data: &[u8],
+    outpath: &Path,
+) -> Result<(), Error> {
+    let mut resource = Resource {
+        identifier: entry.identifier,
+        data: decompress(stored_data, entry.compression)?,
+        ..Default::default()
+    };
+    registry.decode(&mut resource)?;
+    resource.save(outpath, true)?;
+    log::info!(
+        "Extracted {}",
+        outpath.join(resource.get_filename()).to_string_lossy()
+    );
+    Ok(())
+}
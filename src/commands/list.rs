@@ -0,0 +1,247 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde::Serialize;
+use vt7packer::{
+    archive::{self, ArchiveEntry, ArchiveKind, ArchiveReader},
+    codecs::{CodecRegistry, SwordTextCodec},
+    compression::decompress,
+    text, Error, Resource,
+};
+
+use super::{csv_line, OutputFormat};
+
+/// Column by which the directory listing is sorted
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ListSort {
+    /// Order of the archive directory
+    #[default]
+    Directory,
+    Identifier,
+    Offset,
+    Size,
+    CompressedSize,
+    Ratio,
+}
+
+#[derive(Debug, Serialize)]
+struct ListRow {
+    identifier: u32,
+    offset: u32,
+    size: u32,
+    compressed_size: u32,
+    ratio: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codec: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+}
+
+impl ListRow {
+    fn new(entry: &ArchiveEntry, decoder: Option<Decoder>) -> Self {
+        let (codec, language) = match decoder {
+            Some(decoder) => (Some(decoder.codec), decoder.language),
+            None => (None, None),
+        };
+        Self {
+            identifier: entry.identifier,
+            offset: entry.offset,
+            size: entry.size,
+            compressed_size: entry.compressed_size,
+            ratio: match entry.size {
+                0 => 1.0,
+                size => entry.stored_size() as f64 / size as f64,
+            },
+            codec,
+            language,
+        }
+    }
+}
+
+/// Codec decoding an entry, and the language of language files
+#[derive(Debug, Clone)]
+struct Decoder {
+    codec: String,
+    language: Option<String>,
+}
+
+pub fn list(
+    registry: &CodecRegistry,
+    filepath: &Path,
+    format: OutputFormat,
+    sort: ListSort,
+    reverse: bool,
+    detect_codecs: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::open(filepath).map_err(|e| Error::from(e).with_path(filepath))?;
+    let mut archive = archive::open(BufReader::new(file)).map_err(|e| e.with_path(filepath))?;
+    let entries = archive.entries().to_vec();
+
+    let codecs = match detect_codecs {
+        true => Some(
+            find_decoders(registry, archive.as_mut(), &entries)
+                .map_err(|e| e.with_path(filepath))?,
+        ),
+        false => None,
+    };
+
+    let mut rows: Vec<ListRow> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| ListRow::new(entry, codecs.as_ref().map(|c| c[i].clone())))
+        .collect();
+
+    match sort {
+        ListSort::Directory => {}
+        ListSort::Identifier => rows.sort_by_key(|r| r.identifier),
+        ListSort::Offset => rows.sort_by_key(|r| r.offset),
+        ListSort::Size => rows.sort_by_key(|r| r.size),
+        ListSort::CompressedSize => rows.sort_by_key(|r| r.compressed_size),
+        ListSort::Ratio => rows.sort_by(|a, b| a.ratio.total_cmp(&b.ratio)),
+    }
+    if reverse {
+        rows.reverse();
+    }
+
+    let mut out = io::stdout().lock();
+    match format {
+        OutputFormat::Table => {
+            writeln!(out, "{}", describe_kind(archive.kind()))?;
+            write_table(&mut out, &rows, detect_codecs)?
+        }
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&rows)?)?,
+        OutputFormat::Csv => write_csv(&mut out, &rows, detect_codecs)?,
+    }
+    Ok(())
+}
+
+/// Returns the name of the codec which would decode each entry, and the
+/// language of language files. Unlike the directory itself, this needs the
+/// decompressed contents of every entry.
+fn find_decoders(
+    registry: &CodecRegistry,
+    archive: &mut dyn ArchiveReader,
+    entries: &[ArchiveEntry],
+) -> Result<Vec<Decoder>, Error> {
+    let mut codecs = vec![];
+    // Only keep as many entries in memory as can be worked on in parallel
+    for chunk in entries.chunks(rayon::current_num_threads()) {
+        let stored_data = chunk
+            .iter()
+            .map(|entry| archive.read_raw(entry))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let chunk_codecs = chunk
+            .par_iter()
+            .zip(stored_data.into_par_iter())
+            .map(|(entry, data)| {
+                let mut resource = Resource {
+                    identifier: entry.identifier,
+                    data: decompress(&data, entry.compression)
+                        .map_err(|e| e.with_resource(entry.identifier))?,
+                    ..Default::default()
+                };
+                let Some(codec) = registry.find_decoder(&resource) else {
+                    return Ok(Decoder {
+                        codec: String::new(),
+                        language: None,
+                    });
+                };
+                let codec_name = codec.to_string();
+                // Only language files are decoded, all others would take too long
+                let mut language = None;
+                if codec_name == SwordTextCodec::default().to_string() {
+                    codec
+                        .decode(&mut resource, registry)
+                        .map_err(|e| e.with_resource(entry.identifier))?;
+                    language = text::resource_language(&resource).map(|l| l.to_string());
+                }
+                Ok(Decoder {
+                    codec: codec_name,
+                    language,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        codecs.extend(chunk_codecs);
+    }
+    Ok(codecs)
+}
+
+/// Returns a line describing the archive header, with the timestamp of VT7A
+/// archives as date if it looks like one.
+fn describe_kind(kind: ArchiveKind) -> String {
+    match kind {
+        ArchiveKind::Vt7a { version, timestamp } => {
+            let mut description = format!(
+                "VT7A version {}, timestamp 0x{:08x}",
+                u32::from(version),
+                timestamp
+            );
+            if let Some(date) = archive::format_timestamp(timestamp) {
+                description.push_str(&format!(" ({})", date));
+            }
+            description
+        }
+        ArchiveKind::Osa => "AUFS archive".to_string(),
+    }
+}
+
+fn write_table(out: &mut impl Write, rows: &[ListRow], with_codecs: bool) -> io::Result<()> {
+    let mut header = format!(
+        "{:>10} | {:>10} | {:>10} | {:>10} | {:>7}",
+        "Identifier", "Offset", "Size", "Compressed", "Ratio"
+    );
+    if with_codecs {
+        header.push_str(" | Codec");
+    }
+    writeln!(out, "{}", header)?;
+
+    for row in rows {
+        let mut line = format!(
+            "{:>10} | {:>10} | {:>10} | {:>10} | {:>6.1}%",
+            format!("{:08x}", row.identifier),
+            format!("0x{:08x}", row.offset),
+            row.size,
+            row.compressed_size,
+            row.ratio * 100.0
+        );
+        if let Some(codec) = &row.codec {
+            line.push_str(" | ");
+            line.push_str(codec);
+            if let Some(language) = &row.language {
+                line.push_str(&format!(" ({})", language));
+            }
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+fn write_csv(out: &mut impl Write, rows: &[ListRow], with_codecs: bool) -> io::Result<()> {
+    let mut header = vec!["identifier", "offset", "size", "compressed_size", "ratio"];
+    if with_codecs {
+        header.push("codec");
+        header.push("language");
+    }
+    writeln!(out, "{}", csv_line(&header))?;
+
+    for row in rows {
+        let mut fields = vec![
+            format!("{:08x}", row.identifier),
+            row.offset.to_string(),
+            row.size.to_string(),
+            row.compressed_size.to_string(),
+            format!("{:.4}", row.ratio),
+        ];
+        if let Some(codec) = &row.codec {
+            fields.push(codec.clone());
+            fields.push(row.language.clone().unwrap_or_default());
+        }
+        writeln!(out, "{}", csv_line(&fields))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use vt7packer::{
+    codecs::{CodecRegistry, Vt7aCodec},
+    compression::CompressionSpec,
+    Error, Resource,
+};
+
+mod convert;
+mod diff;
+mod extract;
+mod list;
+mod modify;
+mod patch;
+mod stats;
+mod text;
+mod verify;
+
+pub use convert::convert_version;
+pub use diff::diff;
+pub use extract::extract;
+pub use list::{list, ListSort};
+pub use modify::{add, remove, replace};
+pub use patch::{apply_patch, make_patch};
+pub use stats::statistics;
+pub use text::{
+    check_text, export_po, export_sheet, export_xliff, import_po, import_sheet, import_xliff,
+    SheetFormat,
+};
+pub use verify::verify;
+
+/// Output format of commands printing listings
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns for reading in a terminal
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+/// Joins fields into one line of CSV, quoting them where necessary.
+fn csv_line<S: AsRef<str>>(fields: &[S]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parses a hexadecimal identifier like `0000a123` or `0xa123`.
+fn parse_identifier(identifier: &str) -> Result<u32, Error> {
+    let digits = identifier.trim_start_matches("0x");
+    u32::from_str_radix(digits, 16)
+        .map_err(|_| Error::parsing(format!("'{}' is not an identifier", identifier)))
+}
+
+/// Parses a compression override like `0000a123=zstd:19` or `webm=none`. The
+/// key is either an identifier or a format or extension of files.
+pub fn parse_compression_override(value: &str) -> Result<(String, CompressionSpec), Error> {
+    let (key, spec) = value.split_once('=').ok_or_else(|| {
+        Error::parsing(format!(
+            "'{}' should look like <identifier or format>=<compression>",
+            value
+        ))
+    })?;
+    let key = match parse_identifier(key) {
+        Ok(identifier) => format!("{:08x}", identifier),
+        Err(_) => key.to_string(),
+    };
+    Ok((key, spec.parse()?))
+}
+
+/// Streams `write` into a temporary file in `outpath`. On success the file is
+/// renamed to the filename returned by `write`, otherwise it is removed. The
+/// file can be read as well, e.g. to verify what was written.
+fn write_output(
+    outpath: &Path,
+    tempname: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<String, Error>,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let temppath = outpath.join(format!("{}.tmp", tempname));
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temppath)?;
+    let mut file = BufWriter::new(file);
+    let result = write(&mut file).and_then(|filename| {
+        file.flush()?;
+        Ok(filename)
+    });
+    drop(file);
+    match result {
+        Ok(filename) => {
+            let path = outpath.join(filename);
+            fs::rename(&temppath, &path)?;
+            Ok(path)
+        }
+        Err(err) => {
+            fs::remove_file(&temppath)?;
+            Err(err.into())
+        }
+    }
+}
+
+/// Reads a file from disk and decodes it, adding the path to any error.
+fn load_decoded(registry: &CodecRegistry, filepath: &Path) -> Result<Resource, Error> {
+    let mut resource = Resource {
+        data: fs::read(filepath).map_err(|e| Error::from(e).with_path(filepath))?,
+        ..Default::default()
+    };
+    registry
+        .decode(&mut resource)
+        .map_err(|e| e.with_path(filepath))?;
+    Ok(resource)
+}
+
+pub fn decode(
+    registry: &CodecRegistry,
+    filepath: &Path,
+    outpath: &Path,
+    save_hidden: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let archive = load_decoded(registry, filepath)?;
+    // Files have no identifier outside of an archive. VT7A archives are named
+    // after their timestamp, so several of them can be decoded into one folder.
+    let filename = match Vt7aCodec::manifest_timestamp(&archive) {
+        Some(timestamp) => Resource {
+            identifier: timestamp,
+            label: archive.label.clone(),
+            format: archive.format.clone(),
+            extension: archive.extension.clone(),
+            ..Default::default()
+        }
+        .get_filename(),
+        None => archive.get_filename(),
+    };
+    archive.save_as(outpath, &filename, save_hidden)?;
+    log::info!(
+        "Unpacked files to: {}",
+        &outpath.join(&filename).to_string_lossy()
+    );
+    Ok(())
+}
+
+pub fn encode(
+    registry: &CodecRegistry,
+    filepath: &Path,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut archive =
+        Resource::load_from(filepath).map_err(|e| Error::from(e).with_path(filepath))?;
+
+    // The final filename is only known after encoding, so stream into a
+    // temporary file first
+    let archivepath = write_output(outpath, &archive.get_filename(), |file| {
+        registry
+            .encode_into(filepath.parent().unwrap(), &mut archive, file)
+            .map_err(|e| e.with_path(filepath))?;
+        Ok(archive.get_filename())
+    })?;
+    log::info!("Packed files to: {}", &archivepath.to_string_lossy());
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::{fs, io::BufReader, path::Path};
+
+use vt7packer::{
+    archive::{self, EntryChange},
+    codecs::CodecRegistry,
+    Error, Resource,
+};
+
+use super::{parse_identifier, write_output};
+
+pub fn replace(
+    registry: &CodecRegistry,
+    filepath: &Path,
+    identifier: &str,
+    replacement_path: &Path,
+    outpath: &Path,
+    raw: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let change = EntryChange::Replace {
+        identifier: parse_identifier(identifier)?,
+        data: load_entry_file(registry, replacement_path, raw)
+            .map_err(|e| e.with_path(replacement_path))?,
+    };
+    apply(filepath, outpath, &[change])
+}
+
+pub fn add(
+    registry: &CodecRegistry,
+    filepath: &Path,
+    identifier: &str,
+    entry_path: &Path,
+    outpath: &Path,
+    raw: bool,
+    uncompressed: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let change = EntryChange::Add {
+        identifier: parse_identifier(identifier)?,
+        data: load_entry_file(registry, entry_path, raw).map_err(|e| e.with_path(entry_path))?,
+        compressed: !uncompressed,
+    };
+    apply(filepath, outpath, &[change])
+}
+
+pub fn remove(
+    filepath: &Path,
+    identifiers: &[String],
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let changes = identifiers
+        .iter()
+        .map(|identifier| {
+            Ok(EntryChange::Remove {
+                identifier: parse_identifier(identifier)?,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    apply(filepath, outpath, &changes)
+}
+
+/// Writes a copy of the archive with the changes applied into `outpath`.
+pub(super) fn apply(
+    filepath: &Path,
+    outpath: &Path,
+    changes: &[EntryChange],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let filename = filepath.file_name().unwrap().to_string_lossy().to_string();
+    let source = fs::File::open(filepath).map_err(|e| Error::from(e).with_path(filepath))?;
+    let archivepath = write_output(outpath, &filename, |file| {
+        archive::apply_changes(BufReader::new(source), file, changes)
+            .map_err(|e| e.with_path(filepath))?;
+        Ok(filename.clone())
+    })?;
+    log::info!("Packed files to: {}", &archivepath.to_string_lossy());
+    Ok(())
+}
+
+/// Reads the new contents of an entry. Unless `raw` is set, the file is
+/// encoded with the codec matching its name first, e.g. a `.sword_text.json`
+/// file is converted back into the game format.
+fn load_entry_file(registry: &CodecRegistry, path: &Path, raw: bool) -> Result<Vec<u8>, Error> {
+    let data = fs::read(path)?;
+    if raw {
+        return Ok(data);
+    }
+
+    let mut resource = Resource {
+        data,
+        ..Default::default()
+    };
+    // Files written by `decode` are named after their identifier, others
+    // only need the tags, e.g. `english.sword_text.json`
+    let filename = path.file_name().unwrap().to_string_lossy();
+    if resource.parse_filename(&filename).is_err() {
+        let (_, tags) = filename.split_once('.').unwrap_or((&filename, ""));
+        resource.parse_tags(tags)?;
+    }
+
+    registry.load_subresources(path.parent().unwrap(), &mut resource)?;
+    registry.encode(&mut resource)?;
+    Ok(resource.data)
+}
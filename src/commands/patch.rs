@@ -0,0 +1,75 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use vt7packer::{
+    archive::{Patch, PatchData},
+    Error,
+};
+
+use super::write_output;
+
+pub fn make_patch(
+    original_path: &Path,
+    modified_path: &Path,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let patch = Patch::create_from_files(original_path, modified_path)?;
+    log_changes(&patch);
+
+    let stem = original_path.file_stem().unwrap().to_string_lossy();
+    let filename = format!("{}.vt7p", stem);
+    let patchpath = write_output(outpath, &filename, |file| {
+        patch.write(file)?;
+        Ok(filename.clone())
+    })?;
+    log::info!("Wrote patch to: {}", patchpath.to_string_lossy());
+    Ok(())
+}
+
+pub fn apply_patch(
+    filepath: &Path,
+    patch_path: &Path,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let patch_file = File::open(patch_path).map_err(|e| Error::from(e).with_path(patch_path))?;
+    let patch = Patch::read(BufReader::new(patch_file)).map_err(|e| e.with_path(patch_path))?;
+    log_changes(&patch);
+
+    let filename = filepath.file_name().unwrap().to_string_lossy().to_string();
+    let source = File::open(filepath).map_err(|e| Error::from(e).with_path(filepath))?;
+    let archivepath = write_output(outpath, &filename, |file| {
+        // The patched archive is read back to verify it, the buffer is empty
+        patch
+            .apply(BufReader::new(source), file.get_mut())
+            .map_err(|e| e.with_path(filepath))?;
+        Ok(filename.clone())
+    })?;
+    log::info!("Packed files to: {}", &archivepath.to_string_lossy());
+    Ok(())
+}
+
+fn log_changes(patch: &Patch) {
+    let (mut replaced, mut added) = (0, 0);
+    for patch_entry in &patch.entries {
+        let action = match patch_entry.data {
+            PatchData::Copy(_) => continue,
+            PatchData::Replace(_) => {
+                replaced += 1;
+                "Replaced"
+            }
+            PatchData::Add(_) => {
+                added += 1;
+                "Added"
+            }
+        };
+        log::debug!("{} {:08x}", action, patch_entry.entry.identifier);
+    }
+    for identifier in &patch.removed {
+        log::debug!("Removed {:08x}", identifier);
+    }
+    log::info!(
+        "{} replaced, {} added and {} removed entries",
+        replaced,
+        added,
+        patch.removed.len()
+    );
+}
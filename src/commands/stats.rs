@@ -0,0 +1,313 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufReader, Cursor, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+use vt7packer::{
+    archive::{self, ArchiveEntry, ArchiveReader},
+    codecs::CodecRegistry,
+    text::resource_language,
+    Error, Resource,
+};
+
+use super::{csv_line, load_decoded, OutputFormat};
+
+/// Entries with the same extension and format.
+#[derive(Debug, Default, Serialize)]
+struct GroupStats {
+    extension: Option<String>,
+    format: Option<String>,
+    count: usize,
+    /// Entries which are not saved by `decode` unless requested
+    hidden: usize,
+    /// Entries no codec recognized
+    unknown: usize,
+    size: u64,
+    average_size: f64,
+    max_size: u64,
+    /// Bytes the entries occupy inside their archive
+    compressed_size: u64,
+    average_compressed_size: f64,
+    max_compressed_size: u64,
+    ratio: f64,
+}
+
+impl GroupStats {
+    fn add(&mut self, resource: &Resource, size: u64, compressed_size: u64) {
+        self.count += 1;
+        self.hidden += usize::from(resource.hidden);
+        self.unknown += usize::from(resource.extension.as_deref() == Some("raw"));
+        self.size += size;
+        self.max_size = self.max_size.max(size);
+        self.compressed_size += compressed_size;
+        self.max_compressed_size = self.max_compressed_size.max(compressed_size);
+    }
+
+    fn merge(&mut self, other: &GroupStats) {
+        self.count += other.count;
+        self.hidden += other.hidden;
+        self.unknown += other.unknown;
+        self.size += other.size;
+        self.max_size = self.max_size.max(other.max_size);
+        self.compressed_size += other.compressed_size;
+        self.max_compressed_size = self.max_compressed_size.max(other.max_compressed_size);
+    }
+
+    /// Fills in the averages and the ratio once all entries are added.
+    fn finish(&mut self) {
+        if self.count > 0 {
+            self.average_size = self.size as f64 / self.count as f64;
+            self.average_compressed_size = self.compressed_size as f64 / self.count as f64;
+        }
+        self.ratio = match self.size {
+            0 => 1.0,
+            size => self.compressed_size as f64 / size as f64,
+        };
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct LanguageRow {
+    file: String,
+    identifier: u32,
+    language: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Statistics {
+    groups: Vec<GroupStats>,
+    total: GroupStats,
+    languages: Vec<LanguageRow>,
+}
+
+type GroupKey = (Option<String>, Option<String>);
+
+pub fn statistics(
+    registry: &CodecRegistry,
+    filepaths: &[impl AsRef<Path>],
+    recursive: bool,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut groups: BTreeMap<GroupKey, GroupStats> = BTreeMap::new();
+    let mut statistics = Statistics::default();
+    for filepath in filepaths {
+        let filepath = filepath.as_ref();
+        let resource = load_decoded(registry, filepath)?;
+        let file = File::open(filepath).map_err(|e| Error::from(e).with_path(filepath))?;
+        match archive::open(BufReader::new(file)) {
+            Ok(mut archive) => collect(
+                archive.as_mut(),
+                &resource,
+                &filepath.display().to_string(),
+                recursive,
+                &mut groups,
+                &mut statistics.languages,
+            )
+            .map_err(|e| e.with_path(filepath))?,
+            // Other files count as a single uncompressed entry
+            Err(_) => {
+                let size = filepath.metadata()?.len();
+                group(&mut groups, &resource).add(&resource, size, size);
+            }
+        }
+    }
+
+    for mut stats in groups.into_values() {
+        stats.finish();
+        statistics.total.merge(&stats);
+        statistics.groups.push(stats);
+    }
+    statistics.total.finish();
+
+    let mut out = io::stdout().lock();
+    match format {
+        OutputFormat::Table => write_table(&mut out, &statistics)?,
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&statistics)?)?,
+        OutputFormat::Csv => write_csv(&mut out, &statistics)?,
+    }
+    Ok(())
+}
+
+fn group<'a>(
+    groups: &'a mut BTreeMap<GroupKey, GroupStats>,
+    resource: &Resource,
+) -> &'a mut GroupStats {
+    groups
+        .entry((resource.extension.clone(), resource.format.clone()))
+        .or_insert_with(|| GroupStats {
+            extension: resource.extension.clone(),
+            format: resource.format.clone(),
+            ..Default::default()
+        })
+}
+
+/// Adds the entries of an archive to their groups. The sizes are taken from
+/// the archive directory, the groups from the decoded entries. When recursing,
+/// nested archives are replaced by their entries, so no byte is counted twice.
+fn collect(
+    archive: &mut dyn ArchiveReader,
+    decoded: &Resource,
+    location: &str,
+    recursive: bool,
+    groups: &mut BTreeMap<GroupKey, GroupStats>,
+    languages: &mut Vec<LanguageRow>,
+) -> Result<(), Error> {
+    let entries: HashMap<u32, ArchiveEntry> = archive
+        .entries()
+        .iter()
+        .map(|entry| (entry.identifier, *entry))
+        .collect();
+    for resource in &decoded.subresources {
+        let Some(entry) = entries.get(&resource.identifier) else {
+            continue;
+        };
+        if !recursive || resource.subresources.is_empty() {
+            group(groups, resource).add(resource, entry.size.into(), entry.stored_size().into());
+            if let Some(language) = resource_language(resource) {
+                languages.push(LanguageRow {
+                    file: location.to_string(),
+                    identifier: resource.identifier,
+                    language: language.to_string(),
+                });
+            }
+        } else {
+            let data = archive
+                .read(entry)
+                .map_err(|e| e.with_resource(entry.identifier))?;
+            let mut nested =
+                archive::open(Cursor::new(data)).map_err(|e| e.with_resource(entry.identifier))?;
+            collect(
+                nested.as_mut(),
+                resource,
+                &format!("{}/{:08x}", location, entry.identifier),
+                recursive,
+                groups,
+                languages,
+            )
+            .map_err(|e| e.with_resource(entry.identifier))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_table(out: &mut impl Write, statistics: &Statistics) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>10} | {:>10} | {:>6} | {:>6} | {:>7} | {:>12} | {:>10} | {:>10} | {:>12} | {:>10} | {:>10} | {:>7}",
+        "Extension",
+        "Format",
+        "Amount",
+        "Hidden",
+        "Unknown",
+        "Size",
+        "Average",
+        "Max",
+        "Compressed",
+        "Average",
+        "Max",
+        "Ratio"
+    )?;
+    for stats in &statistics.groups {
+        write_row(
+            out,
+            stats.extension.as_deref().unwrap_or_default(),
+            stats.format.as_deref().unwrap_or_default(),
+            stats,
+        )?;
+    }
+    write_row(out, "total", "", &statistics.total)?;
+
+    if !statistics.languages.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "{:>10} | {:>10} | File", "Identifier", "Language")?;
+        for row in &statistics.languages {
+            writeln!(
+                out,
+                "{:>10} | {:>10} | {}",
+                format!("{:08x}", row.identifier),
+                row.language,
+                row.file
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn write_row(
+    out: &mut impl Write,
+    extension: &str,
+    format: &str,
+    stats: &GroupStats,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>10} | {:>10} | {:>6} | {:>6} | {:>7} | {:>12} | {:>10.0} | {:>10} | {:>12} | {:>10.0} | {:>10} | {:>6.1}%",
+        extension,
+        format,
+        stats.count,
+        stats.hidden,
+        stats.unknown,
+        stats.size,
+        stats.average_size,
+        stats.max_size,
+        stats.compressed_size,
+        stats.average_compressed_size,
+        stats.max_compressed_size,
+        stats.ratio * 100.0
+    )
+}
+
+/// Writes the groups and their total as one table. The languages are left
+/// out, as they would need a table of their own.
+fn write_csv(out: &mut impl Write, statistics: &Statistics) -> io::Result<()> {
+    let header = [
+        "extension",
+        "format",
+        "count",
+        "hidden",
+        "unknown",
+        "size",
+        "average_size",
+        "max_size",
+        "compressed_size",
+        "average_compressed_size",
+        "max_compressed_size",
+        "ratio",
+    ];
+    writeln!(out, "{}", csv_line(&header))?;
+    for stats in &statistics.groups {
+        write_csv_row(
+            out,
+            stats.extension.as_deref().unwrap_or_default(),
+            stats.format.as_deref().unwrap_or_default(),
+            stats,
+        )?;
+    }
+    write_csv_row(out, "total", "", &statistics.total)
+}
+
+fn write_csv_row(
+    out: &mut impl Write,
+    extension: &str,
+    format: &str,
+    stats: &GroupStats,
+) -> io::Result<()> {
+    let fields = [
+        extension.to_string(),
+        format.to_string(),
+        stats.count.to_string(),
+        stats.hidden.to_string(),
+        stats.unknown.to_string(),
+        stats.size.to_string(),
+        format!("{:.1}", stats.average_size),
+        stats.max_size.to_string(),
+        stats.compressed_size.to_string(),
+        format!("{:.1}", stats.average_compressed_size),
+        stats.max_compressed_size.to_string(),
+        format!("{:.4}", stats.ratio),
+    ];
+    writeln!(out, "{}", csv_line(&fields))
+}
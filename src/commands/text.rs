@@ -0,0 +1,513 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use vt7packer::{
+    archive::EntryChange, codecs::CodecRegistry, codecs::TextLine, text, text::TextImport, Error,
+    Resource,
+};
+
+use super::{csv_line, load_decoded, modify::apply, parse_identifier, OutputFormat};
+
+/// Number of identifiers listed in a report before it is cut short
+const REPORTED_IDENTIFIERS: usize = 10;
+
+pub fn export_po(
+    registry: &CodecRegistry,
+    reference_path: &Path,
+    translation_path: Option<&Path>,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (reference_identifier, reference) = load_text_lines(registry, reference_path)?;
+    let translation = translation_path
+        .map(|path| load_text_lines(registry, path))
+        .transpose()?;
+
+    if let Some((_, lines)) = &translation {
+        report_untranslatable(&reference, lines);
+    }
+
+    let po = text::export_po(
+        &reference,
+        translation
+            .as_ref()
+            .map(|(identifier, lines)| (*identifier, lines.as_slice())),
+    );
+    let filename = match &translation {
+        Some((identifier, _)) => format!("{:08x}.po", identifier),
+        None => format!("{:08x}.pot", reference_identifier),
+    };
+    let popath = outpath.join(filename);
+    fs::write(&popath, po).map_err(|e| Error::from(e).with_path(&popath))?;
+    log::info!(
+        "Exported {} lines to: {}",
+        reference.len(),
+        popath.display()
+    );
+    Ok(())
+}
+
+pub fn import_po(
+    registry: &CodecRegistry,
+    reference_path: &Path,
+    po_path: &Path,
+    identifier: Option<&str>,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (_, reference) = load_text_lines(registry, reference_path)?;
+    let po = fs::read_to_string(po_path).map_err(|e| Error::from(e).with_path(po_path))?;
+    let import = text::import_po(&reference, &po).map_err(|e| e.with_path(po_path))?;
+
+    save_import(registry, import, identifier, po_path, false, outpath)
+}
+
+pub fn export_xliff(
+    registry: &CodecRegistry,
+    reference_path: &Path,
+    translation_path: Option<&Path>,
+    source_language: &str,
+    target_language: Option<&str>,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (reference_identifier, reference) = load_text_lines(registry, reference_path)?;
+    let translation = translation_path
+        .map(|path| load_text_lines(registry, path))
+        .transpose()?;
+
+    if let Some((_, lines)) = &translation {
+        report_untranslatable(&reference, lines);
+    }
+    let target_language = match (target_language, &translation) {
+        (None, Some((_, lines))) => {
+            let language = text::detect_language(lines).ok_or_else(|| {
+                Error::parsing("Cannot detect the language, give it with --target-language")
+                    .with_path(translation_path.unwrap())
+            })?;
+            log::info!("Detected {} as target language", language);
+            Some(language.tag())
+        }
+        (target_language, _) => target_language,
+    };
+
+    let xliff = text::export_xliff(
+        &reference,
+        source_language,
+        translation
+            .as_ref()
+            .map(|(identifier, lines)| (*identifier, lines.as_slice())),
+        target_language,
+    )?;
+    let filename = match &translation {
+        Some((identifier, _)) => format!("{:08x}.xlf", identifier),
+        None => format!("{:08x}.template.xlf", reference_identifier),
+    };
+    let xliff_path = outpath.join(filename);
+    fs::write(&xliff_path, xliff).map_err(|e| Error::from(e).with_path(&xliff_path))?;
+    log::info!(
+        "Exported {} lines to: {}",
+        reference.len(),
+        xliff_path.display()
+    );
+    Ok(())
+}
+
+pub fn import_xliff(
+    registry: &CodecRegistry,
+    reference_path: &Path,
+    xliff_path: &Path,
+    identifier: Option<&str>,
+    json: bool,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (_, reference) = load_text_lines(registry, reference_path)?;
+    let xliff = fs::read_to_string(xliff_path).map_err(|e| Error::from(e).with_path(xliff_path))?;
+    let import = text::import_xliff(&reference, &xliff).map_err(|e| e.with_path(xliff_path))?;
+    save_import(registry, import, identifier, xliff_path, !json, outpath)
+}
+
+/// Reports the problems of an imported translation and saves its lines as
+/// `.sword_text.json`, or with `encode` as binary language file.
+fn save_import(
+    registry: &CodecRegistry,
+    import: TextImport,
+    identifier: Option<&str>,
+    path: &Path,
+    encode: bool,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let identifier = match identifier {
+        Some(identifier) => parse_identifier(identifier)?,
+        None => import.identifier.ok_or_else(|| {
+            Error::parsing(format!(
+                "{} does not name the language it translates, use --identifier",
+                path.display()
+            ))
+        })?,
+    };
+
+    report(
+        &import.missing,
+        "lines have no translation and keep the reference text",
+    );
+    report(
+        &import.fuzzy,
+        "lines are fuzzy or outdated and keep the reference text",
+    );
+    if !import.extra.is_empty() {
+        log::warn!(
+            "{} translations are not lines of the reference and were dropped: {}",
+            import.extra.len(),
+            shorten(import.extra.iter().cloned())
+        );
+    }
+
+    let mut resource = Resource {
+        identifier,
+        format: Some("sword_text".to_string()),
+        extension: Some("json".to_string()),
+        data: serde_json::to_vec_pretty(&import.lines)?,
+        ..Default::default()
+    };
+    if encode {
+        registry.encode(&mut resource)?;
+    }
+    resource.save(outpath, true)?;
+    log::info!(
+        "Imported {} lines to: {}",
+        import.lines.len(),
+        outpath.join(resource.get_filename()).display()
+    );
+    Ok(())
+}
+
+/// Issue found by `check-text`, with the language it was found in
+#[derive(Debug, Serialize)]
+struct CheckRow {
+    language: u32,
+    #[serde(flatten)]
+    issue: text::TextIssue,
+}
+
+/// Checks translations against the reference language and prints every
+/// issue. Returns whether no issues were found.
+pub fn check_text(
+    registry: &CodecRegistry,
+    reference_path: &Path,
+    translation_paths: &[PathBuf],
+    max_length_ratio: f64,
+    format: OutputFormat,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let (_, reference) = load_text_lines(registry, reference_path)?;
+    let mut rows = vec![];
+    for path in translation_paths {
+        let (language, translation) = load_text_lines(registry, path)?;
+        rows.extend(
+            text::check_translation(&reference, &translation, max_length_ratio)
+                .into_iter()
+                .map(|issue| CheckRow { language, issue }),
+        );
+    }
+
+    let mut out = io::stdout().lock();
+    match format {
+        OutputFormat::Table => {
+            if !rows.is_empty() {
+                writeln!(
+                    out,
+                    "{:>10} | {:>10} | {:<18} | Message",
+                    "Language", "Identifier", "Issue"
+                )?;
+            }
+            for row in &rows {
+                writeln!(
+                    out,
+                    "{:>10} | {:>10} | {:<18} | {}",
+                    format!("{:08x}", row.language),
+                    format!("{:08x}", row.issue.identifier),
+                    row.issue.kind.to_string(),
+                    row.issue.message
+                )?;
+            }
+            match rows.len() {
+                0 => log::info!("No issues found"),
+                count => log::warn!("{} issues found", count),
+            }
+        }
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&rows)?)?,
+        OutputFormat::Csv => {
+            writeln!(
+                out,
+                "{}",
+                csv_line(&["language", "identifier", "issue", "message"])
+            )?;
+            for row in &rows {
+                writeln!(
+                    out,
+                    "{}",
+                    csv_line(&[
+                        format!("{:08x}", row.language),
+                        format!("{:08x}", row.issue.identifier),
+                        row.issue.kind.to_string(),
+                        row.issue.message.clone(),
+                    ])
+                )?;
+            }
+        }
+    }
+    Ok(rows.is_empty())
+}
+
+/// Decoded language file and its lines
+type Language = (Resource, Vec<TextLine>);
+
+/// Format of the spreadsheets written by `export-sheet`
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum SheetFormat {
+    /// Comma separated values
+    #[default]
+    Csv,
+    /// Tab separated values
+    Tsv,
+}
+
+impl SheetFormat {
+    /// Guesses the format from the extension of a sheet, defaulting to CSV.
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("tsv") => Self::Tsv,
+            _ => Self::Csv,
+        }
+    }
+
+    fn delimiter(self) -> char {
+        match self {
+            Self::Csv => ',',
+            Self::Tsv => '\t',
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Tsv => "tsv",
+        }
+    }
+}
+
+pub fn export_sheet(
+    registry: &CodecRegistry,
+    filepath: &Path,
+    format: SheetFormat,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (languages, _) = load_languages(registry, filepath)?;
+    let languages: Vec<(u32, &[TextLine])> = languages
+        .iter()
+        .map(|(resource, lines)| (resource.identifier, lines.as_slice()))
+        .collect();
+    report(
+        &text::partial_lines(&languages),
+        "lines only exist in some languages",
+    );
+
+    let sheet = text::export_sheet(&languages, format.delimiter());
+    let filename = filepath.file_name().unwrap().to_string_lossy();
+    let (name, _) = filename.split_once('.').unwrap_or((&filename, ""));
+    let sheetpath = outpath.join(format!("{}.{}", name, format.extension()));
+    fs::write(&sheetpath, sheet).map_err(|e| Error::from(e).with_path(&sheetpath))?;
+    log::info!(
+        "Exported {} languages to: {}",
+        languages.len(),
+        sheetpath.display()
+    );
+    Ok(())
+}
+
+pub fn import_sheet(
+    registry: &CodecRegistry,
+    filepath: &Path,
+    sheet_path: &Path,
+    outpath: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (languages, decoded_dir) = load_languages(registry, filepath)?;
+    let originals: Vec<(u32, &[TextLine])> = languages
+        .iter()
+        .map(|(resource, lines)| (resource.identifier, lines.as_slice()))
+        .collect();
+    let sheet = fs::read_to_string(sheet_path).map_err(|e| Error::from(e).with_path(sheet_path))?;
+    let delimiter = SheetFormat::from_path(sheet_path).delimiter();
+    let import =
+        text::import_sheet(&originals, &sheet, delimiter).map_err(|e| e.with_path(sheet_path))?;
+
+    report(
+        &import.missing,
+        "lines have no row in the sheet and keep their text",
+    );
+    for (language, identifier) in &import.added {
+        log::warn!(
+            "Line {:08x} was added to language {:08x}",
+            identifier,
+            language
+        );
+    }
+    if !import.extra.is_empty() {
+        log::warn!(
+            "{} columns are not languages of the archive and were ignored: {}",
+            import.extra.len(),
+            shorten(import.extra.iter().cloned())
+        );
+    }
+    let updated: Vec<(u32, &[TextLine])> = import
+        .languages
+        .iter()
+        .map(|(identifier, lines)| (*identifier, lines.as_slice()))
+        .collect();
+    report(
+        &text::partial_lines(&updated),
+        "lines only exist in some languages",
+    );
+
+    // The decoded resources are kept, so a label stays part of the filename
+    let changed: Vec<Resource> = import
+        .languages
+        .iter()
+        .zip(&languages)
+        .filter(|((_, lines), (_, original))| lines != original)
+        .map(|((_, lines), (original, _))| {
+            Ok(Resource {
+                data: serde_json::to_vec_pretty(lines)?,
+                ..original.clone()
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+    if changed.is_empty() {
+        log::info!("No language was changed");
+        return Ok(());
+    }
+
+    match decoded_dir {
+        // Decoded archives are updated in place, so they can be packed with `encode`
+        Some(dirpath) => {
+            for resource in &changed {
+                resource.save(&dirpath, true)?;
+                log::info!(
+                    "Updated: {}",
+                    dirpath.join(resource.get_filename()).display()
+                );
+            }
+        }
+        None => {
+            let changes = changed
+                .into_iter()
+                .map(|mut resource| {
+                    registry.encode(&mut resource)?;
+                    Ok(EntryChange::Replace {
+                        identifier: resource.identifier,
+                        data: resource.data,
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            log::info!("Replacing {} languages", changes.len());
+            apply(filepath, outpath, &changes)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the languages of a text.vt7a, either decoded (`.vt7a3.json`) or as
+/// stored by the game. For decoded archives the folder holding the language
+/// files is returned as well.
+fn load_languages(
+    registry: &CodecRegistry,
+    path: &Path,
+) -> Result<(Vec<Language>, Option<PathBuf>), Error> {
+    let (archive, decoded_dir) = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => {
+            let mut archive =
+                Resource::load_from(path).map_err(|e| Error::from(e).with_path(path))?;
+            let parent = path.parent().unwrap();
+            registry
+                .load_subresources(parent, &mut archive)
+                .map_err(|e| e.with_path(path))?;
+            let dirpath = parent.join(archive.get_dirname());
+            (archive, Some(dirpath))
+        }
+        _ => (load_decoded(registry, path)?, None),
+    };
+
+    let languages = archive
+        .subresources
+        .iter()
+        .filter(|resource| resource.format.as_deref() == Some("sword_text"))
+        .map(|resource| {
+            let lines = serde_json::from_slice(&resource.data)
+                .map_err(|e| Error::from(e).with_resource(resource.identifier))?;
+            Ok((resource.clone(), lines))
+        })
+        .collect::<Result<Vec<_>, Error>>()
+        .map_err(|e| e.with_path(path))?;
+    if languages.is_empty() {
+        return Err(Error::parsing(format!(
+            "{} contains no language files",
+            path.display()
+        )));
+    }
+    Ok((languages, decoded_dir))
+}
+
+/// Reads the lines of a language file, either decoded (`.sword_text.json`) or
+/// as stored in text.vt7a.
+fn load_text_lines(registry: &CodecRegistry, path: &Path) -> Result<(u32, Vec<TextLine>), Error> {
+    let mut resource = Resource::load_from(path).map_err(|e| Error::from(e).with_path(path))?;
+    if resource.format.as_deref() != Some("sword_text") {
+        registry
+            .decode(&mut resource)
+            .map_err(|e| e.with_path(path))?;
+    }
+    if resource.format.as_deref() != Some("sword_text") {
+        return Err(Error::parsing(format!(
+            "{} is not a language file",
+            path.display()
+        )));
+    }
+    let lines =
+        serde_json::from_slice(&resource.data).map_err(|e| Error::from(e).with_path(path))?;
+    Ok((resource.identifier, lines))
+}
+
+fn report_untranslatable(reference: &[TextLine], translation: &[TextLine]) {
+    let untranslatable: Vec<u32> = translation
+        .iter()
+        .filter(|line| !reference.iter().any(|r| r.identifier == line.identifier))
+        .map(|line| line.identifier)
+        .collect();
+    report(
+        &untranslatable,
+        "lines of the translation are not in the reference and were not exported",
+    );
+}
+
+fn report(identifiers: &[u32], description: &str) {
+    if !identifiers.is_empty() {
+        log::warn!(
+            "{} {}: {}",
+            identifiers.len(),
+            description,
+            shorten(identifiers.iter().map(|i| format!("{:08x}", i)))
+        );
+    }
+}
+
+/// Joins the first few items of a report.
+fn shorten(items: impl ExactSizeIterator<Item = String>) -> String {
+    let count = items.len();
+    let mut shortened: Vec<String> = items.take(REPORTED_IDENTIFIERS).collect();
+    if count > REPORTED_IDENTIFIERS {
+        shortened.push(format!("and {} more", count - REPORTED_IDENTIFIERS));
+    }
+    shortened.join(", ")
+}
@@ -0,0 +1,251 @@
+use std::{
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use vt7packer::{
+    archive::{self, ArchiveEntry},
+    codecs::CodecRegistry,
+    compression::decompress,
+    Error, Resource,
+};
+
+/// A difference between an original file and its re-encoded version.
+#[derive(Debug)]
+struct Mismatch {
+    /// Identifiers from the outermost archive to the entry
+    resources: Vec<u32>,
+    /// Codec which decoded the entry
+    codec: Option<String>,
+    description: String,
+    /// Whether the game would see different contents
+    fatal: bool,
+}
+
+impl Mismatch {
+    fn location(&self) -> String {
+        match self.resources.is_empty() {
+            true => "(file)".to_string(),
+            false => self
+                .resources
+                .iter()
+                .map(|r| format!("{:08x}", r))
+                .collect::<Vec<_>>()
+                .join("/"),
+        }
+    }
+}
+
+pub fn verify(
+    registry: &CodecRegistry,
+    filepaths: &[PathBuf],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut failed_files = 0;
+    for filepath in filepaths {
+        match verify_file(registry, filepath) {
+            Ok(true) => {}
+            Ok(false) => failed_files += 1,
+            Err(err) => {
+                log::error!("{}", err);
+                failed_files += 1;
+            }
+        }
+    }
+
+    if failed_files > 0 {
+        return Err(format!("{} files failed to round-trip", failed_files).into());
+    }
+    Ok(())
+}
+
+/// Decodes and encodes a file in memory and reports every difference to the
+/// original. Returns whether the contents survived the round-trip.
+fn verify_file(registry: &CodecRegistry, filepath: &Path) -> Result<bool, Error> {
+    let original = fs::read(filepath).map_err(|e| Error::from(e).with_path(filepath))?;
+
+    let mut resource = Resource {
+        data: original.clone(),
+        ..Default::default()
+    };
+    registry
+        .decode(&mut resource)
+        .map_err(|e| e.with_path(filepath))?;
+    registry
+        .encode(&mut resource)
+        .map_err(|e| e.with_path(filepath))?;
+
+    if resource.data == original {
+        log::info!("{}: byte-identical", filepath.display());
+        return Ok(true);
+    }
+
+    let mut mismatches = vec![];
+    compare(registry, &original, &resource.data, &[], &mut mismatches)
+        .map_err(|e| e.with_path(filepath))?;
+    let fatal = mismatches.iter().filter(|m| m.fatal).count();
+
+    log::info!(
+        "{}: {} bytes re-encoded to {} bytes, {} entries differ in content",
+        filepath.display(),
+        original.len(),
+        resource.data.len(),
+        fatal
+    );
+    for mismatch in &mismatches {
+        let message = match &mismatch.codec {
+            Some(codec) => format!(
+                "  {} ({}): {}",
+                mismatch.location(),
+                codec,
+                mismatch.description
+            ),
+            None => format!("  {}: {}", mismatch.location(), mismatch.description),
+        };
+        match mismatch.fatal {
+            true => log::warn!("{}", message),
+            false => log::info!("{}", message),
+        }
+    }
+    Ok(fatal == 0)
+}
+
+fn compare(
+    registry: &CodecRegistry,
+    original: &[u8],
+    encoded: &[u8],
+    resources: &[u32],
+    mismatches: &mut Vec<Mismatch>,
+) -> Result<(), Error> {
+    let codec = registry
+        .find_decoder(&Resource {
+            data: original.to_vec(),
+            ..Default::default()
+        })
+        .map(|c| c.to_string());
+    let mismatch = |description: String, fatal: bool| Mismatch {
+        resources: resources.to_vec(),
+        codec: codec.clone(),
+        description,
+        fatal,
+    };
+
+    if !archive::is_archive(original) || !archive::is_archive(encoded) {
+        mismatches.push(mismatch(describe_difference(original, encoded), true));
+        return Ok(());
+    }
+
+    let mut original_archive = archive::open(Cursor::new(original))?;
+    let mut encoded_archive = archive::open(Cursor::new(encoded))?;
+    if original_archive.kind() != encoded_archive.kind() {
+        mismatches.push(mismatch(
+            format!(
+                "header changed from {:?} to {:?}",
+                original_archive.kind(),
+                encoded_archive.kind()
+            ),
+            true,
+        ));
+    }
+
+    let original_entries = original_archive.entries().to_vec();
+    let encoded_entries = encoded_archive.entries().to_vec();
+    let identifiers =
+        |entries: &[ArchiveEntry]| -> Vec<u32> { entries.iter().map(|e| e.identifier).collect() };
+    if identifiers(&original_entries) != identifiers(&encoded_entries) {
+        let missing: Vec<String> = original_entries
+            .iter()
+            .filter(|o| !encoded_entries.iter().any(|e| e.identifier == o.identifier))
+            .map(|o| format!("{:08x}", o.identifier))
+            .collect();
+        let added: Vec<String> = encoded_entries
+            .iter()
+            .filter(|e| {
+                !original_entries
+                    .iter()
+                    .any(|o| o.identifier == e.identifier)
+            })
+            .map(|e| format!("{:08x}", e.identifier))
+            .collect();
+        mismatches.push(mismatch(
+            format!(
+                "directory changed (missing: [{}], added: [{}], or different order)",
+                missing.join(", "),
+                added.join(", ")
+            ),
+            true,
+        ));
+    }
+
+    // Pair entries with the same identifier in order of appearance
+    let mut used = vec![false; encoded_entries.len()];
+    for original_entry in &original_entries {
+        let Some(index) = encoded_entries
+            .iter()
+            .enumerate()
+            .position(|(i, e)| !used[i] && e.identifier == original_entry.identifier)
+        else {
+            continue;
+        };
+        used[index] = true;
+        let encoded_entry = &encoded_entries[index];
+
+        let mut entry_resources = resources.to_vec();
+        entry_resources.push(original_entry.identifier);
+
+        let original_raw = original_archive.read_raw(original_entry)?;
+        let encoded_raw = encoded_archive.read_raw(encoded_entry)?;
+        if original_raw == encoded_raw && original_entry.size == encoded_entry.size {
+            continue;
+        }
+
+        let original_data = decompress(&original_raw, original_entry.compression)
+            .map_err(|e| e.with_resource(original_entry.identifier))?;
+        let encoded_data = decompress(&encoded_raw, encoded_entry.compression)
+            .map_err(|e| e.with_resource(encoded_entry.identifier))?;
+        if original_data != encoded_data {
+            compare(
+                registry,
+                &original_data,
+                &encoded_data,
+                &entry_resources,
+                mismatches,
+            )?;
+        } else if original_entry.is_compressed() != encoded_entry.is_compressed() {
+            mismatches.push(Mismatch {
+                resources: entry_resources,
+                codec: None,
+                description: match original_entry.is_compressed() {
+                    true => "was compressed, now stored".to_string(),
+                    false => "was stored, now compressed".to_string(),
+                },
+                fatal: true,
+            });
+        } else {
+            mismatches.push(Mismatch {
+                resources: entry_resources,
+                codec: None,
+                description: format!(
+                    "same content, compressed to {} instead of {} bytes",
+                    encoded_entry.compressed_size, original_entry.compressed_size
+                ),
+                fatal: false,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn describe_difference(original: &[u8], encoded: &[u8]) -> String {
+    let first_difference = original
+        .iter()
+        .zip(encoded)
+        .position(|(a, b)| a != b)
+        .unwrap_or(original.len().min(encoded.len()));
+    format!(
+        "content differs, {} bytes re-encoded to {} bytes, first difference at 0x{:08x}",
+        original.len(),
+        encoded.len(),
+        first_difference
+    )
+}
@@ -1,14 +1,94 @@
+use std::fmt::Display;
 use std::io::{Read, Write};
+use std::str::FromStr;
 
-use crate::error::{DecodingError, EncodingError};
+use crate::error::Error;
 
+/// Compression algorithm used for an archive entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CompressionFormat {
     None,
     Zlib,
     Zstd,
 }
 
-pub fn decompress(data: &[u8], format: CompressionFormat) -> Result<Vec<u8>, DecodingError> {
+impl Display for CompressionFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Zlib => write!(f, "zlib"),
+            Self::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl FromStr for CompressionFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "zlib" => Ok(Self::Zlib),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(Error::parsing(format!(
+                "Unknown compression {:?}, expected none, zlib or zstd",
+                s
+            ))),
+        }
+    }
+}
+
+/// Compression algorithm and optional level of an entry, written like
+/// `none`, `zlib`, `zstd` or `zstd:19`.
+///
+/// Without a level, an entry keeps the level it had in the original archive
+/// or uses the [`default_level`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionSpec {
+    pub format: CompressionFormat,
+    pub level: Option<i32>,
+}
+
+impl Display for CompressionSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.level {
+            Some(level) => write!(f, "{}:{}", self.format, level),
+            None => write!(f, "{}", self.format),
+        }
+    }
+}
+
+impl FromStr for CompressionSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, level) = match s.split_once(':') {
+            Some((format, level)) => (format, Some(level)),
+            None => (s, None),
+        };
+        let format: CompressionFormat = format.parse()?;
+        let level = match level {
+            Some(_) if format == CompressionFormat::None => {
+                return Err(Error::parsing(format!(
+                    "Compression {:?} cannot have a level",
+                    s
+                )))
+            }
+            Some(level) => {
+                let level = level
+                    .parse()
+                    .map_err(|_| Error::parsing(format!("Invalid compression level in {:?}", s)))?;
+                check_level(format, level)?;
+                Some(level)
+            }
+            None => None,
+        };
+        Ok(Self { format, level })
+    }
+}
+
+/// Decompresses data with the given algorithm.
+pub fn decompress(data: &[u8], format: CompressionFormat) -> Result<Vec<u8>, Error> {
     match format {
         CompressionFormat::None => decompress_none(data),
         CompressionFormat::Zlib => decompress_zlib(data),
@@ -16,46 +96,101 @@ pub fn decompress(data: &[u8], format: CompressionFormat) -> Result<Vec<u8>, Dec
     }
 }
 
-fn decompress_none(data: &[u8]) -> Result<Vec<u8>, DecodingError> {
+fn decompress_none(data: &[u8]) -> Result<Vec<u8>, Error> {
     Ok(data.to_vec())
 }
 
-fn decompress_zlib(data: &[u8]) -> Result<Vec<u8>, DecodingError> {
+fn decompress_zlib(data: &[u8]) -> Result<Vec<u8>, Error> {
     let mut buf = vec![];
     let mut decoder = flate2::read::ZlibDecoder::new(data);
     decoder.read_to_end(&mut buf)?;
     Ok(buf)
 }
 
-fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>, DecodingError> {
+fn decompress_zstd(data: &[u8]) -> Result<Vec<u8>, Error> {
     let mut buf = vec![];
     let mut decoder = zstd::Decoder::new(data)?;
     decoder.read_to_end(&mut buf)?;
     Ok(buf)
 }
 
-pub fn compress(data: &[u8], format: CompressionFormat) -> Result<Vec<u8>, EncodingError> {
+/// Compresses data with the given algorithm and level. The level is ignored
+/// for uncompressed data, see [`default_level`] for the usual level.
+pub fn compress(data: &[u8], format: CompressionFormat, level: i32) -> Result<Vec<u8>, Error> {
+    check_level(format, level)?;
     match format {
         CompressionFormat::None => compress_none(data),
-        CompressionFormat::Zlib => compress_zlib(data),
-        CompressionFormat::Zstd => compress_zstd(data),
+        CompressionFormat::Zlib => compress_zlib(data, level),
+        CompressionFormat::Zstd => compress_zstd(data, level),
+    }
+}
+
+/// Returns the level used for the algorithm unless another one is requested.
+pub fn default_level(format: CompressionFormat) -> i32 {
+    match format {
+        CompressionFormat::None => 0,
+        CompressionFormat::Zlib => 9,
+        CompressionFormat::Zstd => 0,
+    }
+}
+
+/// Returns the levels supported by the algorithm, the most common first.
+pub fn candidate_levels(format: CompressionFormat) -> Vec<i32> {
+    match format {
+        CompressionFormat::None => vec![0],
+        CompressionFormat::Zlib => vec![9, 6, 1, 2, 3, 4, 5, 7, 8, 0],
+        CompressionFormat::Zstd => {
+            let mut levels = vec![3, 19];
+            levels.extend((1..=22).filter(|l| *l != 3 && *l != 19));
+            levels
+        }
     }
 }
 
-fn compress_none(data: &[u8]) -> Result<Vec<u8>, EncodingError> {
+/// Returns the first of `levels` at which `data` compresses to exactly
+/// `compressed`, if any.
+pub fn find_level(
+    data: &[u8],
+    compressed: &[u8],
+    format: CompressionFormat,
+    levels: impl IntoIterator<Item = i32>,
+) -> Option<i32> {
+    levels.into_iter().find(|level| {
+        compress(data, format, *level)
+            .map(|candidate| candidate == compressed)
+            .unwrap_or(false)
+    })
+}
+
+fn compress_none(data: &[u8]) -> Result<Vec<u8>, Error> {
     Ok(data.to_vec())
 }
 
-fn compress_zlib(data: &[u8]) -> Result<Vec<u8>, EncodingError> {
+fn check_level(format: CompressionFormat, level: i32) -> Result<(), Error> {
+    let valid = match format {
+        CompressionFormat::None => true,
+        CompressionFormat::Zlib => (0..=9).contains(&level),
+        CompressionFormat::Zstd => level == 0 || zstd::compression_level_range().contains(&level),
+    };
+    match valid {
+        true => Ok(()),
+        false => Err(Error::parsing(format!(
+            "Invalid {} compression level {}",
+            format, level
+        ))),
+    }
+}
+
+fn compress_zlib(data: &[u8], level: i32) -> Result<Vec<u8>, Error> {
     let mut buf = vec![];
-    let mut encoder = flate2::read::ZlibEncoder::new(data, flate2::Compression::best());
+    let mut encoder = flate2::read::ZlibEncoder::new(data, flate2::Compression::new(level as u32));
     encoder.read_to_end(&mut buf)?;
     Ok(buf)
 }
 
-fn compress_zstd(data: &[u8]) -> Result<Vec<u8>, EncodingError> {
+fn compress_zstd(data: &[u8], level: i32) -> Result<Vec<u8>, Error> {
     let buf = vec![];
-    let mut encoder = zstd::Encoder::new(buf, 0)?;
+    let mut encoder = zstd::Encoder::new(buf, level)?;
     encoder.write_all(data)?;
     Ok(encoder.finish()?)
 }
@@ -0,0 +1,526 @@
+//! Comparison of the decoded contents of resources.
+//!
+//! Instead of only telling that two files differ, [`diff_resources`] compares
+//! them in the way their codec understands them: archives per entry, language
+//! files per line, savegames per variable and XML files per element. All other
+//! files are compared byte by byte.
+//!
+//! ```no_run
+//! use vt7packer::{codecs, diff, Resource};
+//!
+//! let mut old = Resource {
+//!     data: std::fs::read("text.old.vt7a")?,
+//!     ..Default::default()
+//! };
+//! let mut new = Resource {
+//!     data: std::fs::read("text.vt7a")?,
+//!     ..Default::default()
+//! };
+//! codecs::decode(&mut old)?;
+//! codecs::decode(&mut new)?;
+//! if let Some(changes) = diff::diff_resources(&old, &new) {
+//!     println!("{}", serde_json::to_string_pretty(&changes)?);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::codecs::{SaveData, TextLine};
+use crate::resource::Resource;
+
+/// Differences between two versions of a resource.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ContentDiff {
+    /// Entries of an archive, matched by identifier
+    Archive {
+        added: Vec<EntrySummary>,
+        removed: Vec<EntrySummary>,
+        moved: Vec<EntryMove>,
+        changed: Vec<EntryDiff>,
+        /// Differences in the header or the layout of the archive, which do
+        /// not change any entry
+        layout: Vec<LayoutChange>,
+    },
+    /// Lines of a language file, matched by identifier
+    Text {
+        added: Vec<TextLine>,
+        removed: Vec<TextLine>,
+        changed: Vec<LineChange>,
+    },
+    /// Values of a savegame, matched by name
+    Save { changed: Vec<VariableChange> },
+    /// Elements, attributes and texts of an XML file
+    Xml { changed: Vec<XmlChange> },
+    /// Any other file
+    Binary {
+        old_size: u64,
+        new_size: u64,
+        size_delta: i64,
+        /// Offset of the first byte which differs, or the end of the shorter
+        /// file if it is the start of the other one
+        first_difference: u64,
+    },
+}
+
+/// An entry which only exists in one version of an archive.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntrySummary {
+    pub identifier: u32,
+    pub filename: String,
+}
+
+/// An entry whose contents are stored under a new identifier.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryMove {
+    pub old: EntrySummary,
+    pub new: EntrySummary,
+}
+
+/// An entry which exists in both versions of an archive, but differs.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryDiff {
+    pub identifier: u32,
+    /// Filename of the entry in the new version
+    pub filename: String,
+    pub diff: ContentDiff,
+}
+
+/// A field of the manifest of an archive which differs, `null` if it only
+/// exists in one version.
+///
+/// The field is named like `timestamp`, `order` for the order of the entries
+/// in the directory, `data_order` for the order of their data, or like
+/// `0000000a/padding` for a field of a single entry.
+#[derive(Debug, Clone, Serialize)]
+pub struct LayoutChange {
+    pub field: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+/// A line of a language file with a different text.
+#[derive(Debug, Clone, Serialize)]
+pub struct LineChange {
+    pub identifier: u32,
+    pub old: String,
+    pub new: String,
+}
+
+/// A value of a savegame which differs, `null` if it only exists in one
+/// version.
+#[derive(Debug, Clone, Serialize)]
+pub struct VariableChange {
+    pub name: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+/// A difference in an XML file.
+///
+/// The path names the element like `/config/item[2]`, with `/@name` for an
+/// attribute and `/text()` for its text. For elements which only exist in one
+/// version, the other side is `None` and the existing side holds the name.
+#[derive(Debug, Clone, Serialize)]
+pub struct XmlChange {
+    pub path: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Compares two decoded resources and returns their differences, or `None`
+/// if their contents are the same.
+pub fn diff_resources(old: &Resource, new: &Resource) -> Option<ContentDiff> {
+    if same_contents(old, new) {
+        return None;
+    }
+
+    let same_format = old.format == new.format && old.extension == new.extension;
+    let diff = match (old.format.as_deref(), old.extension.as_deref()) {
+        _ if !old.subresources.is_empty() || !new.subresources.is_empty() => {
+            Some(diff_archives(old, new))
+        }
+        (Some("sword_text"), _) if same_format => diff_text(old, new),
+        (Some("sav"), _) if same_format => diff_save(old, new),
+        (_, Some("xml")) if same_format => diff_xml(old, new),
+        _ => None,
+    };
+    // Files which cannot be parsed are still compared byte by byte
+    Some(diff.unwrap_or_else(|| diff_binary(&old.data, &new.data)))
+}
+
+/// Returns whether two resources have the same contents.
+///
+/// Archives are compared by the identifiers and the data of their entries and
+/// by their layout, as the data of an archive is its manifest, which also
+/// holds values like hashes which change on every repack.
+fn same_contents(old: &Resource, new: &Resource) -> bool {
+    if old.subresources.is_empty() && new.subresources.is_empty() {
+        return old.data == new.data;
+    }
+    let old_entries: HashMap<u32, &Resource> =
+        old.subresources.iter().map(|r| (r.identifier, r)).collect();
+    old.subresources.len() == new.subresources.len()
+        && new.subresources.iter().all(|entry| {
+            old_entries
+                .get(&entry.identifier)
+                .is_some_and(|old_entry| same_contents(old_entry, entry))
+        })
+        && diff_layout(old, new).is_empty()
+}
+
+/// Compares the header fields of two archive manifests, the order of their
+/// common entries and how these are stored.
+///
+/// Manifests which cannot be read as JSON have no layout to compare.
+fn diff_layout(old: &Resource, new: &Resource) -> Vec<LayoutChange> {
+    const HEADER_FIELDS: [&str; 2] = ["timestamp", "leading_data"];
+    const ENTRY_FIELDS: [&str; 4] = ["compressed", "compression", "compression_level", "padding"];
+
+    let (Ok(old_manifest), Ok(new_manifest)) = (
+        serde_json::from_slice::<serde_json::Value>(&old.data),
+        serde_json::from_slice::<serde_json::Value>(&new.data),
+    ) else {
+        return vec![];
+    };
+    let mut changes = vec![];
+    let mut compare = |field: String, old: &serde_json::Value, new: &serde_json::Value| {
+        if old != new {
+            changes.push(LayoutChange {
+                field,
+                old: old.clone(),
+                new: new.clone(),
+            });
+        }
+    };
+
+    // Listings only hold the entries, manifests also a header
+    let entries = |manifest: &serde_json::Value| -> Vec<serde_json::Value> {
+        let entries = manifest.get("entries").unwrap_or(manifest);
+        entries.as_array().cloned().unwrap_or_default()
+    };
+    for field in HEADER_FIELDS {
+        let null = serde_json::Value::Null;
+        compare(
+            field.to_string(),
+            old_manifest.get(field).unwrap_or(&null),
+            new_manifest.get(field).unwrap_or(&null),
+        );
+    }
+
+    let identifier = |entry: &serde_json::Value| entry.get("identifier")?.as_u64();
+    let old_entries = entries(&old_manifest);
+    let new_entries = entries(&new_manifest);
+    let old_by_identifier: HashMap<u64, &serde_json::Value> = old_entries
+        .iter()
+        .filter_map(|entry| Some((identifier(entry)?, entry)))
+        .collect();
+    let new_by_identifier: HashMap<u64, &serde_json::Value> = new_entries
+        .iter()
+        .filter_map(|entry| Some((identifier(entry)?, entry)))
+        .collect();
+
+    // Added and removed entries are listed on their own, so only the order of
+    // the entries in both versions is compared
+    let order = |entries: &[serde_json::Value], other: &HashMap<u64, &serde_json::Value>| {
+        let mut common: Vec<&serde_json::Value> = entries
+            .iter()
+            .filter(|entry| identifier(entry).is_some_and(|id| other.contains_key(&id)))
+            .collect();
+        let directory: Vec<String> = common
+            .iter()
+            .filter_map(|entry| Some(format!("{:08x}", identifier(entry)?)))
+            .collect();
+        common.sort_by_key(|entry| entry.get("offset").and_then(|offset| offset.as_u64()));
+        let data: Vec<String> = common
+            .iter()
+            .filter_map(|entry| Some(format!("{:08x}", identifier(entry)?)))
+            .collect();
+        (directory, data)
+    };
+    let (old_directory, old_data) = order(&old_entries, &new_by_identifier);
+    let (new_directory, new_data) = order(&new_entries, &old_by_identifier);
+    compare(
+        "order".to_string(),
+        &old_directory.into(),
+        &new_directory.into(),
+    );
+    compare("data_order".to_string(), &old_data.into(), &new_data.into());
+
+    for new_entry in &new_entries {
+        let Some(id) = identifier(new_entry) else {
+            continue;
+        };
+        let Some(old_entry) = old_by_identifier.get(&id) else {
+            continue;
+        };
+        for field in ENTRY_FIELDS {
+            let null = serde_json::Value::Null;
+            compare(
+                format!("{:08x}/{}", id, field),
+                old_entry.get(field).unwrap_or(&null),
+                new_entry.get(field).unwrap_or(&null),
+            );
+        }
+    }
+    changes
+}
+
+fn diff_archives(old: &Resource, new: &Resource) -> ContentDiff {
+    let summary = |r: &Resource| EntrySummary {
+        identifier: r.identifier,
+        filename: r.get_filename(),
+    };
+    let old_entries: HashMap<u32, &Resource> =
+        old.subresources.iter().map(|r| (r.identifier, r)).collect();
+    let new_entries: HashMap<u32, &Resource> =
+        new.subresources.iter().map(|r| (r.identifier, r)).collect();
+
+    let mut removed: Vec<&Resource> = old
+        .subresources
+        .iter()
+        .filter(|r| !new_entries.contains_key(&r.identifier))
+        .collect();
+    let mut added = vec![];
+    let mut changed = vec![];
+    for entry in &new.subresources {
+        match old_entries.get(&entry.identifier) {
+            None => added.push(entry),
+            Some(old_entry) => {
+                if let Some(diff) = diff_resources(old_entry, entry) {
+                    changed.push(EntryDiff {
+                        identifier: entry.identifier,
+                        filename: entry.get_filename(),
+                        diff,
+                    });
+                }
+            }
+        }
+    }
+
+    // Entries which were removed under one identifier and added under another
+    // one with the same contents were moved
+    let mut removed_by_hash: HashMap<Vec<u8>, Vec<&Resource>> = HashMap::new();
+    for entry in removed.iter().rev() {
+        removed_by_hash.entry(entry.hash()).or_default().push(entry);
+    }
+    let mut moved = vec![];
+    added.retain(|entry| {
+        let Some(old_entry) = removed_by_hash
+            .get_mut(&entry.hash())
+            .and_then(|entries| entries.pop())
+        else {
+            return true;
+        };
+        moved.push(EntryMove {
+            old: summary(old_entry),
+            new: summary(entry),
+        });
+        false
+    });
+    let moved_identifiers: HashSet<u32> = moved.iter().map(|m| m.old.identifier).collect();
+    removed.retain(|entry| !moved_identifiers.contains(&entry.identifier));
+
+    ContentDiff::Archive {
+        added: added.into_iter().map(summary).collect(),
+        removed: removed.into_iter().map(summary).collect(),
+        moved,
+        changed,
+        layout: diff_layout(old, new),
+    }
+}
+
+fn diff_text(old: &Resource, new: &Resource) -> Option<ContentDiff> {
+    let old_lines: Vec<TextLine> = serde_json::from_slice(&old.data).ok()?;
+    let new_lines: Vec<TextLine> = serde_json::from_slice(&new.data).ok()?;
+    let old_texts: HashMap<u32, &str> = old_lines
+        .iter()
+        .map(|line| (line.identifier, line.text.as_str()))
+        .collect();
+    let new_texts: HashMap<u32, &str> = new_lines
+        .iter()
+        .map(|line| (line.identifier, line.text.as_str()))
+        .collect();
+
+    let removed = old_lines
+        .iter()
+        .filter(|line| !new_texts.contains_key(&line.identifier))
+        .cloned()
+        .collect();
+    let mut added = vec![];
+    let mut changed = vec![];
+    for line in &new_lines {
+        match old_texts.get(&line.identifier) {
+            None => added.push(line.clone()),
+            Some(old_text) if *old_text != line.text => changed.push(LineChange {
+                identifier: line.identifier,
+                old: old_text.to_string(),
+                new: line.text.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    Some(ContentDiff::Text {
+        added,
+        removed,
+        changed,
+    })
+}
+
+fn diff_save(old: &Resource, new: &Resource) -> Option<ContentDiff> {
+    let old_save: SaveData = serde_json::from_slice(&old.data).ok()?;
+    let new_save: SaveData = serde_json::from_slice(&new.data).ok()?;
+    let old_variables: BTreeMap<String, serde_json::Value> =
+        old_save.variables().into_iter().collect();
+
+    let mut changed = vec![];
+    let new_variables = new_save.variables();
+    for (name, value) in &new_variables {
+        let old_value = old_variables.get(name).cloned().unwrap_or_default();
+        if old_value != *value {
+            changed.push(VariableChange {
+                name: name.clone(),
+                old: old_value,
+                new: value.clone(),
+            });
+        }
+    }
+    for (name, value) in &old_variables {
+        if !new_variables.iter().any(|(new_name, _)| new_name == name) {
+            changed.push(VariableChange {
+                name: name.clone(),
+                old: value.clone(),
+                new: serde_json::Value::Null,
+            });
+        }
+    }
+    Some(ContentDiff::Save { changed })
+}
+
+/// An element of an XML document.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: BTreeMap<String, String>,
+    /// Text of the element without surrounding whitespace
+    text: String,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn children_named(&self, name: &str) -> Vec<&Element> {
+        self.children.iter().filter(|c| c.name == name).collect()
+    }
+}
+
+fn parse_xml(data: &[u8]) -> Option<Element> {
+    let mut stack: Vec<Element> = vec![Element::default()];
+    for event in EventReader::new(data) {
+        match event.ok()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => stack.push(Element {
+                name: name.local_name,
+                attributes: attributes
+                    .into_iter()
+                    .map(|a| (a.name.local_name, a.value))
+                    .collect(),
+                ..Default::default()
+            }),
+            XmlEvent::EndElement { .. } => {
+                let mut element = stack.pop()?;
+                element.text = element.text.trim().to_string();
+                stack.last_mut()?.children.push(element);
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                stack.last_mut()?.text.push_str(&text);
+            }
+            _ => {}
+        }
+    }
+    // The document itself holds the root element
+    stack.pop()
+}
+
+fn diff_xml(old: &Resource, new: &Resource) -> Option<ContentDiff> {
+    let old_document = parse_xml(&old.data)?;
+    let new_document = parse_xml(&new.data)?;
+    let mut changed = vec![];
+    diff_children(&old_document, &new_document, "", &mut changed);
+    Some(ContentDiff::Xml { changed })
+}
+
+/// Compares two elements which are at the same `path`.
+fn diff_element(old: &Element, new: &Element, path: &str, changed: &mut Vec<XmlChange>) {
+    for (name, value) in &new.attributes {
+        let old_value = old.attributes.get(name);
+        if old_value != Some(value) {
+            changed.push(XmlChange {
+                path: format!("{}/@{}", path, name),
+                old: old_value.cloned(),
+                new: Some(value.clone()),
+            });
+        }
+    }
+    for (name, value) in &old.attributes {
+        if !new.attributes.contains_key(name) {
+            changed.push(XmlChange {
+                path: format!("{}/@{}", path, name),
+                old: Some(value.clone()),
+                new: None,
+            });
+        }
+    }
+    if old.text != new.text {
+        changed.push(XmlChange {
+            path: format!("{}/text()", path),
+            old: Some(old.text.clone()),
+            new: Some(new.text.clone()),
+        });
+    }
+    diff_children(old, new, path, changed);
+}
+
+/// Compares the children of two elements, matching children with the same
+/// name by their position among each other.
+fn diff_children(old: &Element, new: &Element, path: &str, changed: &mut Vec<XmlChange>) {
+    let mut names: Vec<&str> = vec![];
+    for child in new.children.iter().chain(&old.children) {
+        if !names.contains(&child.name.as_str()) {
+            names.push(&child.name);
+        }
+    }
+    for name in names {
+        let old_children = old.children_named(name);
+        let new_children = new.children_named(name);
+        for index in 0..old_children.len().max(new_children.len()) {
+            let child_path = format!("{}/{}[{}]", path, name, index + 1);
+            match (old_children.get(index), new_children.get(index)) {
+                (Some(a), Some(b)) => diff_element(a, b, &child_path, changed),
+                (old_child, new_child) => changed.push(XmlChange {
+                    path: child_path,
+                    old: old_child.map(|c| c.name.clone()),
+                    new: new_child.map(|c| c.name.clone()),
+                }),
+            }
+        }
+    }
+}
+
+fn diff_binary(old: &[u8], new: &[u8]) -> ContentDiff {
+    let first_difference = old
+        .iter()
+        .zip(new)
+        .position(|(a, b)| a != b)
+        .unwrap_or(old.len().min(new.len()));
+    ContentDiff::Binary {
+        old_size: old.len() as u64,
+        new_size: new.len() as u64,
+        size_delta: new.len() as i64 - old.len() as i64,
+        first_difference: first_difference as u64,
+    }
+}
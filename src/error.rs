@@ -1,71 +1,207 @@
 use std::error;
 use std::fmt;
+use std::path::{Path, PathBuf};
 
+/// Error raised while decoding or encoding a resource.
+///
+/// Besides its [`ErrorKind`], the error records where it happened: the file
+/// that was processed, the chain of resource identifiers from the outermost
+/// archive down to the failing entry, the codec working on that entry and the
+/// byte offset at which the failure was detected.
 #[derive(Debug)]
-pub enum DecodingError {
-    ParsingError(String),
-    IOError(std::io::Error),
+pub struct Error {
+    inner: Box<ErrorInner>,
 }
 
-impl fmt::Display for DecodingError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            Self::ParsingError(ref err) => write!(f, "Parsing error: {}", err),
-            Self::IOError(ref err) => write!(f, "IO error: {}", err),
+#[derive(Debug)]
+struct ErrorInner {
+    kind: ErrorKind,
+    path: Option<PathBuf>,
+    resources: Vec<u32>,
+    codec: Option<String>,
+    offset: Option<u64>,
+}
+
+/// The cause of an [`Error`].
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// The data ended before a structure was complete
+    Truncated(String),
+    /// The data does not start with the expected magic bytes
+    BadMagic { expected: String, found: Vec<u8> },
+    /// The format version is not supported
+    UnsupportedVersion(u32),
+    /// A structure points outside of the data
+    OffsetOutOfRange { offset: u64, length: u64, size: u64 },
+    /// The manifest of a decoded archive does not match the files next to it
+    ManifestMismatch(String),
+    /// Any other malformed or unsupported content
+    Parsing(String),
+    /// The JSON of a decoded resource is invalid
+    Json(serde_json::Error),
+    /// Reading or writing a file failed
+    Io(std::io::Error),
+}
+
+impl Error {
+    /// Creates an error without context.
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            inner: Box::new(ErrorInner {
+                kind,
+                path: None,
+                resources: vec![],
+                codec: None,
+                offset: None,
+            }),
         }
     }
-}
 
-impl error::Error for DecodingError {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        match *self {
-            Self::ParsingError(_) => None,
-            Self::IOError(ref err) => Some(err),
+    /// Creates an error for malformed or unsupported content.
+    pub fn parsing(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Parsing(message.into()))
+    }
+
+    /// Creates an error for a manifest that does not match its files.
+    pub fn manifest_mismatch(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::ManifestMismatch(message.into()))
+    }
+
+    /// Sets the file which was processed.
+    pub fn with_path(mut self, path: &Path) -> Self {
+        self.inner.path = Some(path.to_path_buf());
+        self
+    }
+
+    /// Adds the identifier of an enclosing resource. Called from the inside
+    /// out, so the outermost identifier ends up first.
+    pub fn with_resource(mut self, identifier: u32) -> Self {
+        self.inner.resources.insert(0, identifier);
+        self
+    }
+
+    /// Sets the codec which failed, unless a nested codec already did.
+    pub fn with_codec(mut self, codec: impl fmt::Display) -> Self {
+        if self.inner.codec.is_none() {
+            self.inner.codec = Some(codec.to_string());
         }
+        self
     }
-}
 
-impl From<std::io::Error> for DecodingError {
-    fn from(value: std::io::Error) -> Self {
-        Self::IOError(value)
+    /// Sets the byte offset of the failure, unless it is already known.
+    pub fn at_offset(mut self, offset: u64) -> Self {
+        if self.inner.offset.is_none() {
+            self.inner.offset = Some(offset);
+        }
+        self
+    }
+
+    /// Returns the cause of the error.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.inner.kind
+    }
+
+    /// Returns the file which was processed.
+    pub fn path(&self) -> Option<&Path> {
+        self.inner.path.as_deref()
+    }
+
+    /// Returns the identifiers from the outermost archive to the failing entry.
+    pub fn resources(&self) -> &[u32] {
+        &self.inner.resources
+    }
+
+    /// Returns the name of the codec which failed.
+    pub fn codec(&self) -> Option<&str> {
+        self.inner.codec.as_deref()
+    }
+
+    /// Returns the byte offset of the failure inside the innermost resource.
+    pub fn offset(&self) -> Option<u64> {
+        self.inner.offset
     }
 }
 
-#[derive(Debug)]
-pub enum EncodingError {
-    ParsingError(String),
-    DeserializeError(serde_json::Error),
-    IOError(std::io::Error),
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut location: Vec<String> = vec![];
+        if let Some(path) = &self.inner.path {
+            location.push(path.display().to_string());
+        }
+        location.extend(self.inner.resources.iter().map(|r| format!("{:08x}", r)));
+        if !location.is_empty() {
+            write!(f, "{}: ", location.join("/"))?;
+        }
+
+        write!(f, "{}", self.inner.kind)?;
+
+        if let Some(offset) = self.inner.offset {
+            write!(f, " at offset 0x{:08x}", offset)?;
+        }
+        if let Some(codec) = &self.inner.codec {
+            write!(f, " (in {})", codec)?;
+        }
+        Ok(())
+    }
 }
 
-impl fmt::Display for EncodingError {
+impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Self::ParsingError(ref err) => write!(f, "Parsing error: {}", err),
-            Self::DeserializeError(ref err) => write!(f, "Deserialize error: {}", err),
-            Self::IOError(ref err) => write!(f, "IO error: {}", err),
+            Self::Truncated(ref err) => write!(f, "Truncated data: {}", err),
+            Self::BadMagic {
+                ref expected,
+                ref found,
+            } => write!(
+                f,
+                "Bad magic bytes: expected {}, found {:02x?}",
+                expected, found
+            ),
+            Self::UnsupportedVersion(version) => write!(f, "Unsupported version {}", version),
+            Self::OffsetOutOfRange {
+                offset,
+                length,
+                size,
+            } => write!(
+                f,
+                "Offset out of range: {} bytes at 0x{:08x} exceed the {} available bytes",
+                length, offset, size
+            ),
+            Self::ManifestMismatch(ref err) => write!(f, "Manifest mismatch: {}", err),
+            Self::Parsing(ref err) => write!(f, "Parsing error: {}", err),
+            Self::Json(ref err) => write!(f, "JSON error: {}", err),
+            Self::Io(ref err) => write!(f, "IO error: {}", err),
         }
     }
 }
 
-impl error::Error for EncodingError {
+impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        match *self {
-            Self::ParsingError(_) => None,
-            Self::DeserializeError(ref err) => Some(err),
-            Self::IOError(ref err) => Some(err),
+        match self.inner.kind {
+            ErrorKind::Json(ref err) => Some(err),
+            ErrorKind::Io(ref err) => Some(err),
+            _ => None,
         }
     }
 }
 
-impl From<std::io::Error> for EncodingError {
+impl From<ErrorKind> for Error {
+    fn from(value: ErrorKind) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
-        Self::IOError(value)
+        match value.kind() {
+            std::io::ErrorKind::UnexpectedEof => Self::new(ErrorKind::Truncated(value.to_string())),
+            _ => Self::new(ErrorKind::Io(value)),
+        }
     }
 }
 
-impl From<serde_json::Error> for EncodingError {
+impl From<serde_json::Error> for Error {
     fn from(value: serde_json::Error) -> Self {
-        Self::DeserializeError(value)
+        Self::new(ErrorKind::Json(value))
     }
 }
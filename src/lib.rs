@@ -0,0 +1,43 @@
+//! Unpack and pack game files from the Virtual Theatre 7 game engine.
+//!
+//! The central type is [`Resource`], which holds the bytes of a single file
+//! together with the subresources it contains. Codecs turn a resource into a
+//! more useful representation ([`Decoder`]) and back into the format the game
+//! expects ([`Encoder`]).
+//!
+//! ```no_run
+//! use vt7packer::{codecs, Resource};
+//!
+//! let mut archive = Resource {
+//!     data: std::fs::read("text.vt7a")?,
+//!     ..Default::default()
+//! };
+//! codecs::decode(&mut archive)?;
+//! for entry in &archive.subresources {
+//!     println!("{}", entry);
+//! }
+//!
+//! codecs::encode(&mut archive)?;
+//! std::fs::write("text.vt7a", &archive.data)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! The functions in [`codecs`] use the built-in codecs. To add a codec for a
+//! format of your own, register it in a [`CodecRegistry`] and decode and
+//! encode through that registry instead.
+//!
+//! The entries of an archive are decoded and encoded in parallel on the
+//! global [rayon](https://docs.rs/rayon) thread pool. Install a custom pool to
+//! limit the number of threads.
+
+pub mod archive;
+pub mod codecs;
+pub mod compression;
+pub mod diff;
+pub mod error;
+pub mod resource;
+pub mod text;
+
+pub use codecs::{Codec, CodecRegistry, Decoder, Encoder};
+pub use error::{Error, ErrorKind};
+pub use resource::Resource;
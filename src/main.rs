@@ -1,11 +1,13 @@
-mod codecs;
 mod commands;
-mod compression;
-mod error;
-mod resource;
 
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use commands::{ListSort, OutputFormat, SheetFormat};
+use std::{path::PathBuf, process::ExitCode};
+use vt7packer::{
+    codecs::{CodecRegistry, OsaCodec, SwordTextCodec, Vt7aCodec, Vt7aTimestamp, Vt7aVersion},
+    compression::CompressionSpec,
+    Error,
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -18,6 +20,10 @@ struct Cli {
     #[arg(short, long, global = true)]
     output_dir: Option<PathBuf>,
 
+    /// Number of archive entries to work on in parallel (default: number of CPUs)
+    #[arg(short, long, global = true)]
+    jobs: Option<usize>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -40,6 +46,11 @@ enum Commands {
         /// Output all files (even those who are not yet supported)
         #[arg(short, long, global = true)]
         all: bool,
+
+        /// Add the detected language to the filenames of language files,
+        /// e.g. 0000000b.german.sword_text.json
+        #[arg(long)]
+        label_files: bool,
     },
     /// Pack components into a valid VT7 file
     ///
@@ -48,31 +59,379 @@ enum Commands {
     /// path to the archive json file which contains the listing of all archive
     /// contents (this should've been created in `decode`). Afterwards the new
     /// archive will be created in the output directory.
+    ///
+    /// Files in the folder of an archive which are not listed in its json file
+    /// are reported and skipped, unless `--include-new` is given.
+    ///
+    /// The compression of VT7A entries can be changed with `--compression`,
+    /// e.g. `--compression sword_text=zstd:19 --compression webm=none`. This
+    /// takes precedence over the `compression` settings of the json files.
     Encode {
         /// Path to the json file of a decoded VT7 file
         filepath: PathBuf,
+
+        /// Also pack files which are not listed in the json file of an archive
+        #[arg(short, long)]
+        include_new: bool,
+
+        /// Compression of entries selected by identifier, format or extension
+        /// (none, zlib, zstd, optionally with a level like zstd:19)
+        #[arg(short, long, value_name = "KEY=COMPRESSION", value_parser = commands::parse_compression_override)]
+        compression: Vec<(String, CompressionSpec)>,
+
+        /// Third header field of VT7A archives: preserve (the value of the
+        /// original archive), now (the current time) or a number
+        #[arg(short, long, default_value = "preserve")]
+        timestamp: Vt7aTimestamp,
+    },
+    /// Extract single entries from a VT7 archive
+    ///
+    /// Instead of decoding the whole archive, this only writes the given
+    /// entries into the output directory. Entries are selected by their
+    /// identifier, which may contain the wildcards `*` and `?` (e.g. `0000a*`).
+    /// Entries of archives inside the archive are selected by joining the
+    /// identifiers with `/` (e.g. `00012345/0000a123`), this can also be
+    /// appended to the archive path (e.g. `sword.vt7a/00012345`).
+    Extract {
+        /// Path to the VT7 archive
+        filepath: PathBuf,
+
+        /// Identifiers of the entries to extract
+        identifiers: Vec<String>,
+
+        /// Only decompress the entries instead of decoding them
+        #[arg(short, long)]
+        raw: bool,
+    },
+    /// Replace a single entry of a VT7 archive
+    ///
+    /// This creates a copy of the archive in the output directory in which the
+    /// entry with the given identifier has new contents. All other entries are
+    /// copied byte for byte. The replacement file is converted into the game
+    /// format by the codec matching its name (e.g. `english.sword_text.json`),
+    /// just like `encode` would do.
+    Replace {
+        /// Path to the VT7 archive
+        filepath: PathBuf,
+
+        /// Identifier of the entry to replace
+        identifier: String,
+
+        /// Path to the file with the new contents
+        replacement: PathBuf,
+
+        /// Insert the file as it is instead of converting it
+        #[arg(short, long)]
+        raw: bool,
+    },
+    /// Add a new entry to a VT7 archive
+    ///
+    /// This creates a copy of the archive in the output directory with the file
+    /// appended as a new entry. The file is converted like in `replace`.
+    Add {
+        /// Path to the VT7 archive
+        filepath: PathBuf,
+
+        /// Identifier of the new entry
+        identifier: String,
+
+        /// Path to the file to add
+        file: PathBuf,
+
+        /// Insert the file as it is instead of converting it
+        #[arg(short, long)]
+        raw: bool,
+
+        /// Store the entry without compressing it
+        #[arg(short, long)]
+        uncompressed: bool,
+    },
+    /// Remove entries from a VT7 archive
+    ///
+    /// This creates a copy of the archive in the output directory without the
+    /// entries with the given identifiers.
+    Remove {
+        /// Path to the VT7 archive
+        filepath: PathBuf,
+
+        /// Identifiers of the entries to remove
+        #[arg(required = true)]
+        identifiers: Vec<String>,
+    },
+    /// Create a patch with the changes between two VT7 archives
+    ///
+    /// This compares the entries of an original and a modified archive and
+    /// writes the replaced, added and removed entries into a patch file
+    /// (.vt7p) in the output directory. Unchanged entries are not included, so
+    /// the patch can be shared instead of the whole modified archive.
+    MakePatch {
+        /// Path to the original VT7 archive
+        original: PathBuf,
+
+        /// Path to the modified VT7 archive
+        modified: PathBuf,
+    },
+    /// Apply a patch created by `make-patch` to a VT7 archive
+    ///
+    /// This creates a copy of the archive in the output directory with the
+    /// changes of the patch. The archive has to be the exact one the patch was
+    /// created from, otherwise nothing is written.
+    ApplyPatch {
+        /// Path to the original VT7 archive
+        filepath: PathBuf,
+
+        /// Path to the patch file
+        patch: PathBuf,
+    },
+    /// Convert a VT7A archive to another version
+    ///
+    /// Version 2 archives (original games) are compressed with zlib, version 3
+    /// archives (Reforged) with zstd. Compressed entries are recompressed with
+    /// the algorithm of the new version, archives inside the archive are
+    /// converted as well. The converted archive is written into the output
+    /// directory under the same name.
+    ConvertVersion {
+        /// Path to the VT7A archive
+        filepath: PathBuf,
+
+        /// Version of the new archive (2 or 3)
+        #[arg(value_parser = clap::value_parser!(u32).range(2..=3))]
+        version: u32,
+    },
+    /// Check that VT7 files survive decoding and encoding unchanged
+    ///
+    /// Every file is decoded and encoded again in memory and the result is
+    /// compared to the original. For archives, every entry whose contents
+    /// change is listed together with the codec which decoded it. Entries
+    /// which only compress to different bytes are listed as well, but are not
+    /// counted as failures.
+    Verify {
+        /// Paths to the VT7 files
+        #[arg(required = true)]
+        filepaths: Vec<PathBuf>,
+    },
+    /// Export a language of text.vt7a for translation with gettext tools
+    ///
+    /// Writes the lines of the reference language (usually English) as PO
+    /// messages, with the line identifier as msgctxt and the reference text as
+    /// msgid. Without a translation this creates a template (.pot), otherwise
+    /// the lines of the translation are filled in as msgstr (.po). Both
+    /// languages can be given as .sword_text.json from `decode` or as entries
+    /// extracted with `extract --raw`.
+    ExportPo {
+        /// Language file whose texts are translated
+        reference: PathBuf,
+
+        /// Language file with the current translation
+        translation: Option<PathBuf>,
+    },
+    /// Create a language of text.vt7a from a translated PO file
+    ///
+    /// Builds a .sword_text.json with the lines of the reference language in
+    /// the same order. Lines which are missing or fuzzy in the PO file keep
+    /// their reference text, and are reported together with messages that do
+    /// not belong to the reference. The result can be packed with `encode` or
+    /// `replace`.
+    ImportPo {
+        /// Language file the PO file was exported from
+        reference: PathBuf,
+
+        /// Translated PO file
+        po: PathBuf,
+
+        /// Identifier of the created language file, if the PO file was not
+        /// exported together with a translation
+        #[arg(short, long)]
+        identifier: Option<String>,
+    },
+    /// Export a language of text.vt7a as XLIFF 2.0 document
+    ///
+    /// Writes every line of the reference language (usually English) as unit
+    /// with the line identifier as id and the reference text as source.
+    /// With a translation its lines are filled in as targets, otherwise this
+    /// creates a template. Both languages can be given as .sword_text.json
+    /// from `decode` or as entries extracted with `extract --raw`.
+    ExportXliff {
+        /// Language file whose texts are translated
+        reference: PathBuf,
+
+        /// Language file with the current translation
+        translation: Option<PathBuf>,
+
+        /// Language of the reference as BCP 47 tag
+        #[arg(long, default_value = "en")]
+        source_language: String,
+
+        /// Language of the translation as BCP 47 tag (default: detected
+        /// from the translation)
+        #[arg(long)]
+        target_language: Option<String>,
+    },
+    /// Create a language of text.vt7a from a translated XLIFF 2.0 document
+    ///
+    /// Builds the language file with the lines of the reference language in
+    /// the same order, ready to be packed with `encode` or `replace`. Every
+    /// line of the reference needs a unit in the document. Units without
+    /// target or in the state "initial" keep their reference text and are
+    /// reported.
+    ImportXliff {
+        /// Language file the document was exported from
+        reference: PathBuf,
+
+        /// Translated XLIFF document
+        xliff: PathBuf,
+
+        /// Identifier of the created language file, if the document was not
+        /// exported together with a translation
+        #[arg(short, long)]
+        identifier: Option<String>,
+
+        /// Write a .sword_text.json instead of the binary language file
+        #[arg(long)]
+        json: bool,
+    },
+    /// Export all languages of text.vt7a into one spreadsheet
+    ///
+    /// Writes a row for every line identifier and a column for every
+    /// language, so the languages can be compared and edited side by side.
+    /// text.vt7a can be given as decoded archive json or as stored by the
+    /// game. Lines which only exist in some languages are reported.
+    ExportSheet {
+        /// text.vt7a or its decoded archive json
+        filepath: PathBuf,
+
+        /// Format of the spreadsheet
+        #[arg(short, long, value_enum, default_value_t)]
+        format: SheetFormat,
     },
-    /// Print statistics about a valid VT7 file
+    /// Apply an edited spreadsheet to the languages of text.vt7a
     ///
-    /// This command counts the amount of files included in an VT7 archive and
-    /// displays them grouped by format and extension.
+    /// Takes the texts of every language column into its language file. A
+    /// decoded archive is updated in place, ready for `encode`. For an archive
+    /// as stored by the game, a copy with the changed languages is written
+    /// into the output directory. Sheets ending in .tsv are read as tab
+    /// separated, all others as comma separated.
+    ImportSheet {
+        /// text.vt7a or its decoded archive json
+        filepath: PathBuf,
+
+        /// Edited spreadsheet
+        sheet: PathBuf,
+    },
+    /// Check translated languages of text.vt7a against a reference language
+    ///
+    /// Reports lines which are missing, not in the reference or empty, whose
+    /// placeholders (like %s) or control characters (like line breaks)
+    /// differ from the reference, whose leading or trailing whitespace
+    /// differs, and which are much longer than the reference. The command
+    /// fails if any issue is found, so it can be used to check mods before
+    /// packing them.
+    CheckText {
+        /// Language file to compare with, usually English
+        reference: PathBuf,
+
+        /// Language files to check
+        #[arg(required = true)]
+        translations: Vec<PathBuf>,
+
+        /// Report lines with more than this many times the characters of the
+        /// reference
+        #[arg(long, default_value_t = 2.0)]
+        max_length_ratio: f64,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Print statistics about valid VT7 files
+    ///
+    /// This command counts the amount of files included in VT7 archives and
+    /// displays them grouped by format and extension, together with their
+    /// total, average and largest size before and after compression, the
+    /// compression ratio and how many of them are hidden or not recognized by
+    /// any codec. The statistics of all given files are added up.
     Stats {
-        /// Path to the VT7 file
+        /// Paths to the VT7 files
+        #[arg(required = true)]
+        filepaths: Vec<PathBuf>,
+
+        /// Output format, the languages of language files are only listed in
+        /// the table and JSON output
+        #[arg(short, long, value_enum, default_value_t)]
+        format: OutputFormat,
+
+        /// Count the entries of archives inside the archives instead of the
+        /// nested archives themselves
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// List the directory of a VT7 archive
+    ///
+    /// This command prints every entry of a VT7 archive (.vt7a or .osa) with
+    /// its identifier, offset, uncompressed size, compressed size (0 if it is
+    /// stored uncompressed) and compression ratio. Only the archive directory
+    /// is read, unless the codecs should be shown as well.
+    List {
+        /// Path to the VT7 archive
         filepath: PathBuf,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t)]
+        format: OutputFormat,
+
+        /// Column to sort the entries by
+        #[arg(short, long, value_enum, default_value_t)]
+        sort: ListSort,
+
+        /// Reverse the sort order
+        #[arg(short, long)]
+        reverse: bool,
+
+        /// Also show the codec which would decode each entry (this needs to
+        /// decompress every entry)
+        #[arg(short, long)]
+        codecs: bool,
     },
-    /// Compare two VT7 files for differences
+    /// Compare two VT7 files or two directories for differences
     ///
     /// This command takes two VT7 archives and compares their contents. It will
     /// report which files are present in only one archive or which files are
-    /// present in both archives, but have different contents.
+    /// present in both archives, but have different contents. Language files
+    /// are compared per line, savegames per variable and XML files per element,
+    /// for all other files the size and first differing byte are shown.
+    ///
+    /// Given two directories, like two game installations or two folders
+    /// created by `decode`, files are paired by their path and compared in the
+    /// same way. Files and entries whose contents were moved to another name
+    /// are reported as moved.
+    ///
+    /// Like diff(1), the exit code is 0 if the inputs have the same contents,
+    /// 1 if they differ and 2 if they could not be compared.
     Diff {
-        /// Path to the first VT7 file
+        /// Path to the first VT7 file or directory
         filepath1: PathBuf,
-        /// Path to the second VT7 file
+        /// Path to the second VT7 file or directory
         filepath2: PathBuf,
+        /// Print the differences as JSON
+        #[arg(long)]
+        json: bool,
     },
 }
 
+impl Commands {
+    /// Returns whether the command writes files into the output directory.
+    fn writes_output(&self) -> bool {
+        !matches!(
+            self,
+            Self::Verify { .. }
+                | Self::Stats { .. }
+                | Self::List { .. }
+                | Self::Diff { .. }
+                | Self::CheckText { .. }
+        )
+    }
+}
+
 fn setup_logger(verbosity: u8) -> Result<(), fern::InitError> {
     let level = match verbosity {
         1 => log::LevelFilter::Debug,
@@ -88,38 +447,223 @@ fn setup_logger(verbosity: u8) -> Result<(), fern::InitError> {
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
-    setup_logger(cli.verbosity)?;
+    // Like diff(1), `diff` uses 1 for differences and 2 for errors
+    let failure = match cli.command {
+        Commands::Diff { .. } => ExitCode::from(2),
+        _ => ExitCode::FAILURE,
+    };
+    if let Err(err) = setup_logger(cli.verbosity) {
+        eprintln!("{}", err);
+        return failure;
+    }
+
+    match run(cli) {
+        Ok(code) => code,
+        Err(err) => {
+            log::error!("{}", err);
+            failure
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    if let Some(jobs) = cli.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()?;
+    }
 
     let outpath: PathBuf = cli
         .output_dir
         .unwrap_or_else(|| std::path::Path::new("out/").to_path_buf());
 
-    if std::fs::create_dir_all(outpath.clone()).is_err() {
-        return Err(Box::new(error::DecodingError::ParsingError(format!(
-            "Output directory ({}) does not exist and could not be created",
-            outpath.display()
-        ))));
+    if cli.command.writes_output() {
+        if let Err(err) = std::fs::create_dir_all(outpath.clone()) {
+            return Err(Box::new(Error::from(err).with_path(&outpath)));
+        }
     }
 
+    let mut registry = CodecRegistry::default();
+
     match &cli.command {
-        Commands::Decode { filepath, all } => {
-            commands::decode(filepath, &outpath, *all)?;
+        Commands::Decode {
+            filepath,
+            all,
+            label_files,
+        } => {
+            registry.register(Box::new(Vt7aCodec {
+                source_archive: Some(filepath.clone()),
+                find_compression_levels: true,
+                ..Default::default()
+            }));
+            registry.register(Box::new(SwordTextCodec {
+                label_files: *label_files,
+            }));
+            commands::decode(&registry, filepath, &outpath, *all)?;
+        }
+        Commands::Encode {
+            filepath,
+            include_new,
+            compression,
+            timestamp,
+        } => {
+            registry.register(Box::new(Vt7aCodec {
+                include_new_files: *include_new,
+                compression: compression.iter().cloned().collect(),
+                timestamp: *timestamp,
+                ..Default::default()
+            }));
+            registry.register(Box::new(OsaCodec {
+                include_new_files: *include_new,
+            }));
+            commands::encode(&registry, filepath, &outpath)?;
+        }
+        Commands::Extract {
+            filepath,
+            identifiers,
+            raw,
+        } => {
+            commands::extract(&registry, filepath, identifiers, &outpath, *raw)?;
+        }
+        Commands::Replace {
+            filepath,
+            identifier,
+            replacement,
+            raw,
+        } => {
+            commands::replace(&registry, filepath, identifier, replacement, &outpath, *raw)?;
         }
-        Commands::Encode { filepath } => {
-            commands::encode(filepath, &outpath)?;
+        Commands::Add {
+            filepath,
+            identifier,
+            file,
+            raw,
+            uncompressed,
+        } => {
+            commands::add(
+                &registry,
+                filepath,
+                identifier,
+                file,
+                &outpath,
+                *raw,
+                *uncompressed,
+            )?;
+        }
+        Commands::Remove {
+            filepath,
+            identifiers,
+        } => {
+            commands::remove(filepath, identifiers, &outpath)?;
+        }
+        Commands::MakePatch { original, modified } => {
+            commands::make_patch(original, modified, &outpath)?;
         }
-        Commands::Stats { filepath } => {
-            commands::statistics(filepath)?;
+        Commands::ApplyPatch { filepath, patch } => {
+            commands::apply_patch(filepath, patch, &outpath)?;
+        }
+        Commands::ConvertVersion { filepath, version } => {
+            commands::convert_version(filepath, Vt7aVersion::try_from(*version)?, &outpath)?;
+        }
+        Commands::ExportPo {
+            reference,
+            translation,
+        } => {
+            commands::export_po(&registry, reference, translation.as_deref(), &outpath)?;
+        }
+        Commands::ImportPo {
+            reference,
+            po,
+            identifier,
+        } => {
+            commands::import_po(&registry, reference, po, identifier.as_deref(), &outpath)?;
+        }
+        Commands::ExportXliff {
+            reference,
+            translation,
+            source_language,
+            target_language,
+        } => {
+            commands::export_xliff(
+                &registry,
+                reference,
+                translation.as_deref(),
+                source_language,
+                target_language.as_deref(),
+                &outpath,
+            )?;
+        }
+        Commands::ImportXliff {
+            reference,
+            xliff,
+            identifier,
+            json,
+        } => {
+            commands::import_xliff(
+                &registry,
+                reference,
+                xliff,
+                identifier.as_deref(),
+                *json,
+                &outpath,
+            )?;
+        }
+        Commands::ExportSheet { filepath, format } => {
+            commands::export_sheet(&registry, filepath, *format, &outpath)?;
+        }
+        Commands::ImportSheet { filepath, sheet } => {
+            commands::import_sheet(&registry, filepath, sheet, &outpath)?;
+        }
+        Commands::Verify { filepaths } => {
+            commands::verify(&registry, filepaths)?;
+        }
+        Commands::Stats {
+            filepaths,
+            format,
+            recursive,
+        } => {
+            commands::statistics(&registry, filepaths, *recursive, *format)?;
+        }
+        Commands::List {
+            filepath,
+            format,
+            sort,
+            reverse,
+            codecs,
+        } => {
+            commands::list(&registry, filepath, *format, *sort, *reverse, *codecs)?;
         }
         Commands::Diff {
             filepath1,
             filepath2,
+            json,
         } => {
-            commands::diff(filepath1, filepath2)?;
+            let differs = commands::diff(&registry, filepath1, filepath2, *json)?;
+            return Ok(match differs {
+                false => ExitCode::SUCCESS,
+                true => ExitCode::from(1),
+            });
+        }
+        Commands::CheckText {
+            reference,
+            translations,
+            max_length_ratio,
+            format,
+        } => {
+            let passed = commands::check_text(
+                &registry,
+                reference,
+                translations,
+                *max_length_ratio,
+                *format,
+            )?;
+            if !passed {
+                return Ok(ExitCode::FAILURE);
+            }
         }
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -2,19 +2,31 @@ use std::{fmt::Display, fs, io, path::Path};
 
 use sha2::{Digest, Sha256};
 
+/// A single file, either from disk or from inside an archive.
 #[derive(Debug, Default, Clone)]
 pub struct Resource {
+    /// Identifier from the archive directory
     pub identifier: u32,
+    /// Name added to the filename, e.g. the language of a language file
+    pub label: Option<String>,
+    /// Format tag set by the codec, e.g. `vt7a3` or `sword_text`
     pub format: Option<String>,
+    /// File extension set by the codec, e.g. `json` or `webp`
     pub extension: Option<String>,
+    /// Contents of the file
     pub data: Vec<u8>,
+    /// Whether the file is skipped on save unless requested
     pub hidden: bool,
+    /// Files contained in this resource (for archives)
     pub subresources: Vec<Self>,
 }
 
 impl Display for Resource {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Resource #{:08x} (", self.identifier,)?;
+        if let Some(x) = &self.label {
+            write!(f, "Label: {}, ", x)?;
+        }
         if let Some(x) = &self.format {
             write!(f, "Format: {}, ", x)?;
         }
@@ -26,9 +38,14 @@ impl Display for Resource {
 }
 
 impl Resource {
+    /// Returns the filename as `identifier[.label][.format][.extension]`.
     pub fn get_filename(&self) -> String {
         let mut filename = format!("{:08x}", self.identifier);
 
+        if let Some(label) = &self.label {
+            filename = format!("{}.{}", filename, label);
+        }
+
         if let Some(tag) = &self.format {
             filename = format!("{}.{}", filename, tag);
         }
@@ -39,19 +56,27 @@ impl Resource {
         filename
     }
 
+    /// Returns the name of the folder holding the subresources.
     pub fn get_dirname(&self) -> String {
         format!("{}.d", self.get_filename())
     }
 
+    /// Writes the resource and all its subresources into `path`.
     pub fn save(&self, path: &Path, save_hidden: bool) -> Result<(), io::Error> {
+        self.save_as(path, &self.get_filename(), save_hidden)
+    }
+
+    /// Like [`Resource::save`], but writes the resource as `filename` and its
+    /// subresources into the folder named after it.
+    pub fn save_as(&self, path: &Path, filename: &str, save_hidden: bool) -> Result<(), io::Error> {
         if !save_hidden && self.hidden {
             return Ok(());
         }
 
         fs::create_dir_all(path)?;
-        fs::write(path.join(self.get_filename()), &self.data)?;
-        if self.subresources.len() > 0 {
-            let dpath = path.join(self.get_dirname());
+        fs::write(path.join(filename), &self.data)?;
+        if !self.subresources.is_empty() {
+            let dpath = path.join(format!("{}.d", filename));
             fs::create_dir_all(&dpath)?;
             for resource in &self.subresources {
                 resource.save(&dpath, save_hidden)?;
@@ -61,36 +86,41 @@ impl Resource {
         Ok(())
     }
 
+    /// Sets identifier, format and extension from a filename created by
+    /// [`Resource::get_filename`].
     pub fn parse_filename(&mut self, filename: &str) -> Result<(), io::Error> {
-        let parts: Vec<&str> = filename.split('.').collect();
+        let (identifier, tags) = filename.split_once('.').unwrap_or((filename, ""));
+
+        self.identifier = u32::from_str_radix(identifier, 16)
+            .map_err(|_| io::Error::other("Filename does not contain identifier"))?;
+        self.parse_tags(tags)
+    }
+
+    /// Sets label, format and extension from the `[[label.]format.]extension`
+    /// part of a filename, e.g. `sword_text.json` or `german.sword_text.json`.
+    pub fn parse_tags(&mut self, tags: &str) -> Result<(), io::Error> {
+        let parts: Vec<&str> = tags.split('.').collect();
 
         match parts.len() {
+            _ if tags.is_empty() => {}
             3 => {
-                self.identifier =
-                    u32::from_str_radix(parts.iter().nth(0).unwrap(), 16).map_err(|_| {
-                        io::Error::new(io::ErrorKind::Other, "Filename does not contain identifier")
-                    })?;
-                self.format = Some(parts.iter().nth(1).unwrap().to_string());
-                self.extension = Some(parts.iter().nth(2).unwrap().to_string());
+                self.label = Some(parts[0].to_string());
+                self.format = Some(parts[1].to_string());
+                self.extension = Some(parts[2].to_string());
             }
             2 => {
-                self.identifier =
-                    u32::from_str_radix(parts.iter().nth(0).unwrap(), 16).map_err(|_| {
-                        io::Error::new(io::ErrorKind::Other, "Filename does not contain identifier")
-                    })?;
-                self.extension = Some(parts.iter().nth(1).unwrap().to_string());
+                self.format = Some(parts[0].to_string());
+                self.extension = Some(parts[1].to_string());
             }
             1 => {
-                self.identifier =
-                    u32::from_str_radix(parts.iter().nth(0).unwrap(), 16).map_err(|_| {
-                        io::Error::new(io::ErrorKind::Other, "Filename does not contain identifier")
-                    })?;
+                self.extension = Some(parts[0].to_string());
             }
-            _ => return Err(io::Error::new(io::ErrorKind::Other, "Wrong filename")),
+            _ => return Err(io::Error::other("Wrong filename")),
         }
         Ok(())
     }
 
+    /// Reads a resource from a file created by [`Resource::save`].
     pub fn load_from(path: &Path) -> Result<Self, io::Error> {
         if !path.is_file() {
             return Err(io::Error::from(io::ErrorKind::NotFound));
@@ -107,6 +137,7 @@ impl Resource {
         Ok(resource)
     }
 
+    /// Returns the SHA-256 hash of the data.
     pub fn hash(&self) -> Vec<u8> {
         let mut hasher = Sha256::new();
         hasher.update(&self.data);
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::codecs::TextLine;
+
+/// Shortest length a reference line is measured with when checking for
+/// overlong translations. Short texts like "OK" often need several times the
+/// characters in other languages, but still fit.
+const SHORT_LINE: usize = 20;
+
+/// Kind of problem found in a translated line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// The reference has the line, but the translation does not
+    Missing,
+    /// The translation has a line the reference does not have
+    Extra,
+    /// The translation is empty, but the reference is not
+    Empty,
+    /// Placeholders like `%s` or `{0}` differ from the reference
+    Placeholders,
+    /// Control characters like line breaks differ from the reference
+    ControlCharacters,
+    /// Leading or trailing whitespace differs from the reference
+    Whitespace,
+    /// The translation is much longer than the reference
+    TooLong,
+}
+
+impl Display for IssueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Missing => "missing",
+            Self::Extra => "extra",
+            Self::Empty => "empty",
+            Self::Placeholders => "placeholders",
+            Self::ControlCharacters => "control_characters",
+            Self::Whitespace => "whitespace",
+            Self::TooLong => "too_long",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A problem found in a translated line.
+#[derive(Debug, Clone, Serialize)]
+pub struct TextIssue {
+    /// Identifier of the line
+    pub identifier: u32,
+    pub kind: IssueKind,
+    /// Description for humans
+    pub message: String,
+}
+
+/// Compares the lines of a translation with those of the reference language
+/// and returns every problem that would show up in the game, ordered by
+/// identifier.
+///
+/// Lines whose translation has more than `max_length_ratio` times the
+/// characters of the reference are reported as too long, as they may not fit
+/// on the screen. References shorter than 20 characters count as 20 long.
+pub fn check_translation(
+    reference: &[TextLine],
+    translation: &[TextLine],
+    max_length_ratio: f64,
+) -> Vec<TextIssue> {
+    let translated: HashMap<u32, &str> = translation
+        .iter()
+        .map(|line| (line.identifier, line.text.as_str()))
+        .collect();
+    let referenced: HashMap<u32, &str> = reference
+        .iter()
+        .map(|line| (line.identifier, line.text.as_str()))
+        .collect();
+
+    let mut issues = vec![];
+    for line in reference {
+        match translated.get(&line.identifier) {
+            Some(text) => check_line(line, text, max_length_ratio, &mut issues),
+            None => issues.push(TextIssue {
+                identifier: line.identifier,
+                kind: IssueKind::Missing,
+                message: "line is missing".to_string(),
+            }),
+        }
+    }
+    for line in translation {
+        if !referenced.contains_key(&line.identifier) {
+            issues.push(TextIssue {
+                identifier: line.identifier,
+                kind: IssueKind::Extra,
+                message: "line is not in the reference".to_string(),
+            });
+        }
+    }
+    issues.sort_by_key(|issue| issue.identifier);
+    issues
+}
+
+fn check_line(
+    reference: &TextLine,
+    text: &str,
+    max_length_ratio: f64,
+    issues: &mut Vec<TextIssue>,
+) {
+    let mut report = |kind, message: String| {
+        issues.push(TextIssue {
+            identifier: reference.identifier,
+            kind,
+            message,
+        })
+    };
+    if reference.text.is_empty() {
+        return;
+    }
+    if text.is_empty() {
+        report(IssueKind::Empty, "translation is empty".to_string());
+        return;
+    }
+
+    let expected = placeholders(&reference.text);
+    let found = placeholders(text);
+    if normalize_placeholders(&expected) != normalize_placeholders(&found) {
+        report(
+            IssueKind::Placeholders,
+            format!(
+                "expected placeholders [{}], found [{}]",
+                expected.join(" "),
+                found.join(" ")
+            ),
+        );
+    }
+
+    let expected = control_characters(&reference.text);
+    let found = control_characters(text);
+    if expected != found {
+        report(
+            IssueKind::ControlCharacters,
+            format!(
+                "expected control characters [{}], found [{}]",
+                expected.join(" "),
+                found.join(" ")
+            ),
+        );
+    }
+
+    let leading = |s: &str| s.len() - s.trim_start().len();
+    let trailing = |s: &str| s.len() - s.trim_end().len();
+    if reference.text[..leading(&reference.text)] != text[..leading(text)]
+        || reference.text[reference.text.len() - trailing(&reference.text)..]
+            != text[text.len() - trailing(text)..]
+    {
+        report(
+            IssueKind::Whitespace,
+            "leading or trailing whitespace differs".to_string(),
+        );
+    }
+
+    let reference_length = reference.text.chars().count();
+    let length = text.chars().count();
+    if length as f64 > reference_length.max(SHORT_LINE) as f64 * max_length_ratio {
+        report(
+            IssueKind::TooLong,
+            format!(
+                "{} characters, the reference has {}",
+                length, reference_length
+            ),
+        );
+    }
+}
+
+/// Returns the printf-style (`%s`, `%1$d`) and brace (`{0}`, `{name}`)
+/// placeholders of a text in the order they appear.
+fn placeholders(text: &str) -> Vec<String> {
+    let mut found = vec![];
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let end = match chars[i] {
+            '%' => chars[i + 1..]
+                .iter()
+                .position(|c| c.is_ascii_alphabetic() || *c == '%' || c.is_whitespace())
+                .map(|p| i + 1 + p)
+                .filter(|end| chars[*end] != '%' || *end == i + 1),
+            '{' => chars[i + 1..]
+                .iter()
+                .position(|c| *c == '}' || *c == '{' || c.is_whitespace())
+                .map(|p| i + 1 + p)
+                .filter(|end| chars[*end] == '}' && *end > i + 1),
+            _ => None,
+        };
+        match end {
+            Some(end) if !chars[end].is_whitespace() => {
+                let placeholder: String = chars[i..=end].iter().collect();
+                // A literal percent sign is no placeholder
+                if placeholder != "%%" {
+                    found.push(placeholder);
+                }
+                i = end + 1;
+            }
+            _ => i += 1,
+        }
+    }
+    found
+}
+
+/// Returns placeholders in a form in which those of a correct translation
+/// equal the reference: printf-style placeholders without position are
+/// numbered in order, as swapping them swaps the values, all others may be
+/// reordered freely.
+fn normalize_placeholders(placeholders: &[String]) -> Vec<String> {
+    let mut position = 0;
+    let mut normalized: Vec<String> = placeholders
+        .iter()
+        .map(|placeholder| {
+            let Some(spec) = placeholder.strip_prefix('%') else {
+                return placeholder.clone();
+            };
+            match spec.split_once('$') {
+                Some(_) => placeholder.clone(),
+                None => {
+                    position += 1;
+                    format!("%{}${}", position, spec)
+                }
+            }
+        })
+        .collect();
+    normalized.sort();
+    normalized
+}
+
+/// Returns the control characters of a text, like line breaks, in the order
+/// they appear.
+fn control_characters(text: &str) -> Vec<String> {
+    text.chars()
+        .filter(|c| c.is_control())
+        .map(|c| c.escape_default().to_string())
+        .collect()
+}
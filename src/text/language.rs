@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+
+use crate::codecs::TextLine;
+use crate::resource::Resource;
+
+/// Languages which can be recognized in language files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Language {
+    English,
+    French,
+    German,
+    Italian,
+    Spanish,
+    Portuguese,
+    Polish,
+    Czech,
+    Turkish,
+    Russian,
+    Japanese,
+    Korean,
+    Chinese,
+}
+
+impl Language {
+    /// Returns the name used in filenames, e.g. `german`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::English => "english",
+            Self::French => "french",
+            Self::German => "german",
+            Self::Italian => "italian",
+            Self::Spanish => "spanish",
+            Self::Portuguese => "portuguese",
+            Self::Polish => "polish",
+            Self::Czech => "czech",
+            Self::Turkish => "turkish",
+            Self::Russian => "russian",
+            Self::Japanese => "japanese",
+            Self::Korean => "korean",
+            Self::Chinese => "chinese",
+        }
+    }
+
+    /// Returns the BCP 47 tag of the language, e.g. `de`.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::French => "fr",
+            Self::German => "de",
+            Self::Italian => "it",
+            Self::Spanish => "es",
+            Self::Portuguese => "pt",
+            Self::Polish => "pl",
+            Self::Czech => "cs",
+            Self::Turkish => "tr",
+            Self::Russian => "ru",
+            Self::Japanese => "ja",
+            Self::Korean => "ko",
+            Self::Chinese => "zh",
+        }
+    }
+}
+
+impl Display for Language {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Characters of Latin-script languages which English does not use, with the
+/// languages using them. Characters shared by several languages count less for
+/// each of them.
+const LATIN_CHARACTERS: &[(char, &[Language])] = {
+    use Language::*;
+    &[
+        ('ä', &[German]),
+        ('ö', &[German, Turkish]),
+        ('ü', &[German, Turkish]),
+        ('ß', &[German]),
+        ('é', &[French, Italian, Spanish, Portuguese, Czech]),
+        ('è', &[French, Italian]),
+        ('ê', &[French, Portuguese]),
+        ('ë', &[French]),
+        ('â', &[French]),
+        ('î', &[French]),
+        ('ï', &[French]),
+        ('ô', &[French, Portuguese]),
+        ('û', &[French]),
+        ('œ', &[French]),
+        ('à', &[French, Italian, Portuguese]),
+        ('ç', &[French, Portuguese, Turkish]),
+        ('ù', &[French, Italian]),
+        ('ì', &[Italian]),
+        ('ò', &[Italian]),
+        ('á', &[Spanish, Portuguese, Czech]),
+        ('í', &[Spanish, Portuguese, Czech]),
+        ('ó', &[Spanish, Portuguese, Polish]),
+        ('ú', &[Spanish, Portuguese, Czech]),
+        ('ñ', &[Spanish]),
+        ('¿', &[Spanish]),
+        ('¡', &[Spanish]),
+        ('ã', &[Portuguese]),
+        ('õ', &[Portuguese]),
+        ('ą', &[Polish]),
+        ('ę', &[Polish]),
+        ('ł', &[Polish]),
+        ('ś', &[Polish]),
+        ('ź', &[Polish]),
+        ('ż', &[Polish]),
+        ('ć', &[Polish]),
+        ('ń', &[Polish]),
+        ('ř', &[Czech]),
+        ('ě', &[Czech]),
+        ('ů', &[Czech]),
+        ('š', &[Czech]),
+        ('č', &[Czech]),
+        ('ž', &[Czech]),
+        ('ý', &[Czech]),
+        ('ň', &[Czech]),
+        ('ť', &[Czech]),
+        ('ď', &[Czech]),
+        ('ğ', &[Turkish]),
+        ('ı', &[Turkish]),
+        ('ş', &[Turkish]),
+    ]
+};
+
+/// Minimum number of letters needed to guess a language.
+const MIN_LETTERS: usize = 20;
+
+/// Share of Latin letters with accents below which a text is taken as
+/// English. Loanwords like "café" appear in English as well.
+const ENGLISH_ACCENT_SHARE: f64 = 0.002;
+
+/// Returns the language of a decoded language file, or `None` for other
+/// resources.
+///
+/// The language is always detected from the lines. There is no built-in
+/// table of the identifiers the game uses for its languages, as they are not
+/// known for any released version, so the identifier of the entry is not
+/// taken into account.
+pub fn resource_language(resource: &Resource) -> Option<Language> {
+    if resource.format.as_deref() != Some("sword_text") {
+        return None;
+    }
+    let lines: Vec<TextLine> = serde_json::from_slice(&resource.data).ok()?;
+    detect_language(&lines)
+}
+
+/// Guesses the language of lines from the frequency of their characters.
+///
+/// Texts in other scripts are recognized by their script. For Latin script,
+/// every language is scored by the accented characters it uses. Returns
+/// `None` if the lines contain too few letters.
+pub fn detect_language(lines: &[TextLine]) -> Option<Language> {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in lines.iter().flat_map(|line| line.text.chars()) {
+        for c in c.to_lowercase() {
+            *counts.entry(c).or_default() += 1;
+        }
+    }
+    let count_where = |predicate: fn(char) -> bool| -> usize {
+        counts
+            .iter()
+            .filter(|(c, _)| predicate(**c))
+            .map(|(_, n)| n)
+            .sum()
+    };
+
+    let hangul = count_where(
+        |c| matches!(c, '\u{1100}'..='\u{11ff}' | '\u{3130}'..='\u{318f}' | '\u{ac00}'..='\u{d7af}'),
+    );
+    let kana = count_where(|c| matches!(c, '\u{3040}'..='\u{30ff}'));
+    let ideographs = count_where(|c| matches!(c, '\u{4e00}'..='\u{9fff}'));
+    let cyrillic = count_where(|c| matches!(c, '\u{0400}'..='\u{04ff}'));
+    let latin = count_where(|c| {
+        c.is_alphabetic() && (c.is_ascii() || LATIN_CHARACTERS.iter().any(|(l, _)| *l == c))
+    });
+
+    // Japanese mixes kana with ideographs, Chinese only uses the latter
+    let japanese = match kana {
+        0 => 0,
+        _ => kana + ideographs,
+    };
+    let scripts = [
+        (hangul, Language::Korean),
+        (japanese, Language::Japanese),
+        (ideographs, Language::Chinese),
+        (cyrillic, Language::Russian),
+    ];
+    let script = scripts
+        .into_iter()
+        .max_by_key(|(letters, _)| *letters)
+        .filter(|(letters, _)| *letters > latin);
+    if let Some((letters, language)) = script {
+        return (letters >= MIN_LETTERS).then_some(language);
+    }
+    if latin < MIN_LETTERS {
+        return None;
+    }
+
+    let mut scores: BTreeMap<Language, f64> = BTreeMap::new();
+    let mut accented = 0;
+    for (c, languages) in LATIN_CHARACTERS {
+        let count = counts.get(c).copied().unwrap_or_default();
+        accented += count;
+        for language in *languages {
+            *scores.entry(*language).or_default() += count as f64 / languages.len() as f64;
+        }
+    }
+    if (accented as f64) < latin as f64 * ENGLISH_ACCENT_SHARE {
+        return Some(Language::English);
+    }
+    scores
+        .into_iter()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(language, _)| language)
+}
@@ -0,0 +1,87 @@
+//! Exchange of the language files from text.vt7a with translation tools.
+//!
+//! The functions work on the lines of decoded `sword_text` resources (see
+//! [`TextLine`](crate::codecs::TextLine)). One language serves as reference,
+//! usually English: its texts are what translators translate from, and its
+//! lines decide which lines a translated language has and in which order.
+
+mod check;
+mod language;
+mod po;
+mod sheet;
+mod xliff;
+
+use std::collections::HashMap;
+
+use crate::codecs::TextLine;
+
+pub use check::{check_translation, IssueKind, TextIssue};
+pub use language::{detect_language, resource_language, Language};
+pub use po::{export_po, import_po};
+pub use sheet::{export_sheet, import_sheet, partial_lines, SheetImport};
+pub use xliff::{export_xliff, import_xliff};
+
+/// Lines of a language built from a translation file.
+#[derive(Debug, Default)]
+pub struct TextImport {
+    /// Identifier of the translated language entry, if the file names it
+    pub identifier: Option<u32>,
+    /// Lines in the order of the reference, with the reference text for every
+    /// line that is missing or fuzzy
+    pub lines: Vec<TextLine>,
+    /// Lines of the reference without a translation
+    pub missing: Vec<u32>,
+    /// Lines whose translation is marked fuzzy or was made for a different
+    /// reference text, these are not used
+    pub fuzzy: Vec<u32>,
+    /// Identifiers (as written in the file) of translations which are not
+    /// lines of the reference
+    pub extra: Vec<String>,
+}
+
+/// Translation of a single line as read from a translation file.
+#[derive(Debug)]
+struct Translation {
+    /// Reference text the translation was made for
+    source: String,
+    target: String,
+    fuzzy: bool,
+}
+
+impl TextImport {
+    /// Fills `lines` with the translations of the reference lines, and
+    /// records which of them are missing or fuzzy.
+    fn merge(&mut self, reference: &[TextLine], translations: &HashMap<u32, Translation>) {
+        for line in reference {
+            let text = match translations.get(&line.identifier) {
+                // Empty lines need no translation
+                _ if line.text.is_empty() => Some(String::new()),
+                None => None,
+                Some(translation) if translation.target.is_empty() => None,
+                Some(translation) if translation.fuzzy || translation.source != line.text => {
+                    self.fuzzy.push(line.identifier);
+                    Some(line.text.clone())
+                }
+                Some(translation) => Some(translation.target.clone()),
+            };
+            let text = text.unwrap_or_else(|| {
+                self.missing.push(line.identifier);
+                line.text.clone()
+            });
+            self.lines.push(TextLine {
+                identifier: line.identifier,
+                text,
+            });
+        }
+    }
+}
+
+/// Formats a line identifier like the filenames of decoded resources.
+fn format_identifier(identifier: u32) -> String {
+    format!("{:08x}", identifier)
+}
+
+/// Parses a line identifier written by [`format_identifier`].
+fn parse_identifier(identifier: &str) -> Result<u32, std::num::ParseIntError> {
+    u32::from_str_radix(identifier, 16)
+}
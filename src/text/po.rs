@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use super::{format_identifier, parse_identifier, TextImport, Translation};
+use crate::codecs::TextLine;
+use crate::error::Error;
+
+/// Header field naming the language entry a PO file translates.
+const IDENTIFIER_FIELD: &str = "X-Vt7-Identifier";
+
+/// Writes a gettext PO file for translating the lines of `reference`.
+///
+/// Every non-empty line becomes a message with its identifier as `msgctxt`
+/// and its text as `msgid`. With a `translation`, given as identifier and lines of the
+/// language entry, its texts become the `msgstr`s and its identifier is kept
+/// in the header, so the file can be imported again without naming the entry.
+/// Without one, all `msgstr`s are empty, which makes the file a POT template.
+///
+/// ```no_run
+/// use vt7packer::codecs::TextLine;
+/// use vt7packer::text;
+///
+/// let english: Vec<TextLine> = serde_json::from_slice(&std::fs::read("0000000a.sword_text.json")?)?;
+/// std::fs::write("text.pot", text::export_po(&english, None))?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn export_po(reference: &[TextLine], translation: Option<(u32, &[TextLine])>) -> String {
+    let translated: HashMap<u32, &str> = translation
+        .map(|(_, lines)| {
+            lines
+                .iter()
+                .map(|line| (line.identifier, line.text.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut header = String::from("Content-Type: text/plain; charset=UTF-8\n");
+    header.push_str("Content-Transfer-Encoding: 8bit\n");
+    header.push_str("X-Generator: vt7packer\n");
+    if let Some((identifier, _)) = translation {
+        header.push_str(&format!(
+            "{}: {}\n",
+            IDENTIFIER_FIELD,
+            format_identifier(identifier)
+        ));
+    }
+
+    let mut po = String::new();
+    write_string(&mut po, "msgid", "");
+    write_string(&mut po, "msgstr", &header);
+    // Empty lines need no translation
+    for line in reference.iter().filter(|line| !line.text.is_empty()) {
+        po.push('\n');
+        write_string(&mut po, "msgctxt", &format_identifier(line.identifier));
+        write_string(&mut po, "msgid", &line.text);
+        let text = translated
+            .get(&line.identifier)
+            .copied()
+            .unwrap_or_default();
+        write_string(&mut po, "msgstr", text);
+    }
+    po
+}
+
+/// Reads a translated PO file written by [`export_po`] and builds the lines
+/// of the translated language.
+///
+/// Like gettext, only messages with a non-empty `msgstr` that are not marked
+/// fuzzy are used. Lines without a usable translation keep the text of the
+/// `reference`, so the game still shows something.
+pub fn import_po(reference: &[TextLine], po: &str) -> Result<TextImport, Error> {
+    let messages = parse_po(po)?;
+    let mut import = TextImport::default();
+
+    let known_identifiers: HashSet<u32> = reference.iter().map(|line| line.identifier).collect();
+    let mut translations: HashMap<u32, Translation> = HashMap::new();
+    for message in &messages {
+        let Some(context) = &message.context else {
+            if message.id.is_empty() {
+                import.identifier = read_header_identifier(&message.text)?;
+            }
+            continue;
+        };
+        let identifier = parse_identifier(context).ok();
+        let known = identifier.filter(|i| known_identifiers.contains(i));
+        match known {
+            Some(identifier) => {
+                let translation = Translation {
+                    source: message.id.clone(),
+                    target: message.text.clone(),
+                    fuzzy: message.fuzzy,
+                };
+                if translations.insert(identifier, translation).is_some() {
+                    return Err(Error::parsing(format!(
+                        "line {}: msgctxt \"{}\" appears more than once",
+                        message.line, context
+                    )));
+                }
+            }
+            None => import.extra.push(context.clone()),
+        }
+    }
+
+    import.merge(reference, &translations);
+    Ok(import)
+}
+
+/// A message of a PO file.
+#[derive(Debug, Default)]
+struct Message {
+    context: Option<String>,
+    id: String,
+    text: String,
+    fuzzy: bool,
+    /// Line on which the message starts
+    line: usize,
+}
+
+/// Keyword whose (possibly continued) string is being read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Keyword {
+    Context,
+    Id,
+    Text,
+    /// Plural forms, which the game does not use
+    Plural,
+}
+
+fn parse_po(po: &str) -> Result<Vec<Message>, Error> {
+    let mut messages = vec![];
+    let mut message = Message::default();
+    let mut keyword: Option<Keyword> = None;
+
+    for (index, line) in po.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            // Obsolete messages and the previous msgid of fuzzy messages are
+            // only kept for the translators
+            if keyword.is_some() {
+                messages.push(std::mem::take(&mut message));
+                keyword = None;
+            }
+            if let Some(flags) = comment.strip_prefix(',') {
+                message.fuzzy |= flags.split(',').any(|flag| flag.trim() == "fuzzy");
+            }
+            continue;
+        }
+
+        let (current, rest) = match line.split_once(char::is_whitespace) {
+            _ if line.starts_with('"') => match keyword {
+                Some(current) => (current, line),
+                None => {
+                    return Err(Error::parsing(format!(
+                        "line {}: string without msgctxt, msgid or msgstr",
+                        number
+                    )))
+                }
+            },
+            Some(("msgctxt", rest)) => (Keyword::Context, rest),
+            Some(("msgid", rest)) => (Keyword::Id, rest),
+            Some(("msgstr" | "msgstr[0]", rest)) => (Keyword::Text, rest),
+            Some((other, rest))
+                if other.starts_with("msgid_plural") || other.starts_with("msgstr[") =>
+            {
+                (Keyword::Plural, rest)
+            }
+            _ => {
+                return Err(Error::parsing(format!(
+                    "line {}: unexpected \"{}\"",
+                    number, line
+                )))
+            }
+        };
+
+        // A msgctxt or msgid after a msgstr starts the next message
+        let starts_message = matches!(current, Keyword::Context | Keyword::Id)
+            && !line.starts_with('"')
+            && matches!(keyword, Some(Keyword::Text | Keyword::Plural));
+        if starts_message {
+            messages.push(std::mem::take(&mut message));
+        }
+        if keyword.is_none() || starts_message {
+            message.line = number;
+        }
+
+        let value = parse_string(rest.trim())
+            .map_err(|e| Error::parsing(format!("line {}: {}", number, e)))?;
+        match current {
+            Keyword::Context => message
+                .context
+                .get_or_insert_with(String::new)
+                .push_str(&value),
+            Keyword::Id => message.id.push_str(&value),
+            Keyword::Text => message.text.push_str(&value),
+            Keyword::Plural => {}
+        }
+        keyword = Some(current);
+    }
+    if keyword.is_some() {
+        messages.push(message);
+    }
+    Ok(messages)
+}
+
+/// Parses a quoted PO string with C escapes.
+fn parse_string(quoted: &str) -> Result<String, String> {
+    let inner = quoted
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string, found {}", quoted))?;
+
+    let mut value = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some('r') => value.push('\r'),
+            Some('"') => value.push('"'),
+            Some('\\') => value.push('\\'),
+            Some(other) => return Err(format!("unknown escape sequence \\{}", other)),
+            None => return Err("string ends with a backslash".to_string()),
+        }
+    }
+    Ok(value)
+}
+
+/// Writes `keyword "value"`, split after every line break like gettext does.
+fn write_string(po: &mut String, keyword: &str, value: &str) {
+    let escape = |s: &str| {
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\t', "\\t")
+            .replace('\r', "\\r")
+    };
+    let lines: Vec<&str> = value.split_inclusive('\n').collect();
+    if lines.len() <= 1 {
+        let _ = writeln!(po, "{} \"{}\"", keyword, escape(value));
+        return;
+    }
+    let _ = writeln!(po, "{} \"\"", keyword);
+    for line in lines {
+        let _ = writeln!(po, "\"{}\"", escape(line));
+    }
+}
+
+fn read_header_identifier(header: &str) -> Result<Option<u32>, Error> {
+    for field in header.lines() {
+        if let Some((name, value)) = field.split_once(':') {
+            if name.trim() == IDENTIFIER_FIELD {
+                return parse_identifier(value.trim()).map(Some).map_err(|_| {
+                    Error::parsing(format!(
+                        "{} \"{}\" is not an identifier",
+                        IDENTIFIER_FIELD,
+                        value.trim()
+                    ))
+                });
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(texts: &[(u32, &str)]) -> Vec<TextLine> {
+        texts
+            .iter()
+            .map(|(identifier, text)| TextLine {
+                identifier: *identifier,
+                text: text.to_string(),
+            })
+            .collect()
+    }
+
+    fn reference() -> Vec<TextLine> {
+        lines(&[
+            (1, "He said \"Hello\""),
+            (2, ""),
+            (3, "First line\nSecond line\n"),
+            (4, "Tab\there, return\r\nand a backslash \\"),
+        ])
+    }
+
+    #[test]
+    fn translations_survive_export_and_import() {
+        let translation = lines(&[
+            (1, "Er sagte \"Hallo\""),
+            (2, ""),
+            (3, "Erste Zeile\nZweite Zeile\n"),
+            (4, "Tab\thier, Wagenrücklauf\r\nund ein Backslash \\"),
+        ]);
+        let po = export_po(&reference(), Some((0x0b, &translation)));
+        assert!(po.contains("msgstr \"\"\n\"Erste Zeile\\n\"\n\"Zweite Zeile\\n\"\n"));
+
+        let import = import_po(&reference(), &po).unwrap();
+        assert_eq!(import.identifier, Some(0x0b));
+        assert_eq!(import.lines, translation);
+        assert!(import.missing.is_empty());
+        assert!(import.fuzzy.is_empty());
+        assert!(import.extra.is_empty());
+    }
+
+    #[test]
+    fn templates_keep_the_reference_text() {
+        let po = export_po(&reference(), None);
+        let import = import_po(&reference(), &po).unwrap();
+        assert_eq!(import.identifier, None);
+        assert_eq!(import.lines, reference());
+        assert_eq!(import.missing, vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn fuzzy_translations_are_not_used() {
+        let translation = lines(&[(1, "Er sagte \"Hallo\"")]);
+        let po = export_po(&reference(), Some((0x0b, &translation)))
+            .replace("msgctxt \"00000001\"", "#, fuzzy\nmsgctxt \"00000001\"");
+        let import = import_po(&reference(), &po).unwrap();
+        assert_eq!(import.lines[0], reference()[0]);
+        assert_eq!(import.fuzzy, vec![1]);
+        assert_eq!(import.missing, vec![3, 4]);
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{format_identifier, parse_identifier};
+use crate::codecs::TextLine;
+use crate::error::Error;
+
+/// Title of the first column, which holds the line identifiers.
+const IDENTIFIER_COLUMN: &str = "identifier";
+
+/// Lines of all languages rebuilt from a spreadsheet.
+#[derive(Debug, Default)]
+pub struct SheetImport {
+    /// Identifier and lines of every language, in the order they were given
+    pub languages: Vec<(u32, Vec<TextLine>)>,
+    /// Lines which have no row in the sheet and keep their text
+    pub missing: Vec<u32>,
+    /// Language and line of non-empty cells for lines the language did not
+    /// have, these are appended to the language
+    pub added: Vec<(u32, u32)>,
+    /// Columns which do not belong to a language and were ignored
+    pub extra: Vec<String>,
+}
+
+/// Writes the lines of several languages as one spreadsheet, separated by
+/// `delimiter` (`,` for CSV, `\t` for TSV).
+///
+/// The sheet has a row for every line identifier and a column for every
+/// language, titled with the identifier of its language entry. Cells of lines
+/// a language does not have stay empty. Fields are quoted like in RFC 4180,
+/// so texts may contain line breaks.
+///
+/// ```no_run
+/// use vt7packer::codecs::TextLine;
+/// use vt7packer::text;
+///
+/// let english: Vec<TextLine> = serde_json::from_slice(&std::fs::read("0000000a.sword_text.json")?)?;
+/// let german: Vec<TextLine> = serde_json::from_slice(&std::fs::read("0000000b.sword_text.json")?)?;
+/// let sheet = text::export_sheet(&[(0x0000000a, &english), (0x0000000b, &german)], ',');
+/// std::fs::write("text.csv", sheet)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn export_sheet(languages: &[(u32, &[TextLine])], delimiter: char) -> String {
+    let texts: Vec<HashMap<u32, &str>> = languages
+        .iter()
+        .map(|(_, lines)| {
+            lines
+                .iter()
+                .map(|line| (line.identifier, line.text.as_str()))
+                .collect()
+        })
+        .collect();
+
+    let mut header = vec![IDENTIFIER_COLUMN.to_string()];
+    header.extend(languages.iter().map(|(id, _)| format_identifier(*id)));
+    let mut sheet = String::new();
+    write_row(&mut sheet, &header, delimiter);
+    for identifier in line_identifiers(languages) {
+        let mut row = vec![format_identifier(identifier)];
+        row.extend(texts.iter().map(|texts| {
+            texts
+                .get(&identifier)
+                .copied()
+                .unwrap_or_default()
+                .to_string()
+        }));
+        write_row(&mut sheet, &row, delimiter);
+    }
+    sheet
+}
+
+/// Reads a spreadsheet written by [`export_sheet`] and applies its texts to
+/// the lines of `languages`.
+///
+/// Every language keeps its lines and their order, only the texts are taken
+/// from the sheet. Empty cells make empty lines, except for lines the
+/// language does not have, which are only added for non-empty cells.
+/// Languages without column are returned unchanged.
+pub fn import_sheet(
+    languages: &[(u32, &[TextLine])],
+    sheet: &str,
+    delimiter: char,
+) -> Result<SheetImport, Error> {
+    let mut rows = parse_rows(sheet, delimiter)?.into_iter();
+    let (_, header) = rows
+        .next()
+        .ok_or_else(|| Error::parsing("The sheet is empty"))?;
+    if header.first().map(String::as_str) != Some(IDENTIFIER_COLUMN) {
+        return Err(Error::parsing(format!(
+            "The first column should be titled \"{}\"",
+            IDENTIFIER_COLUMN
+        )));
+    }
+
+    let mut import = SheetImport::default();
+    // Column of every language in the sheet
+    let mut columns: HashMap<u32, usize> = HashMap::new();
+    for (column, title) in header.iter().enumerate().skip(1) {
+        let language = parse_identifier(title)
+            .ok()
+            .filter(|id| languages.iter().any(|(language, _)| language == id));
+        match language {
+            Some(language) => {
+                if columns.insert(language, column).is_some() {
+                    return Err(Error::parsing(format!(
+                        "Column \"{}\" appears more than once",
+                        title
+                    )));
+                }
+            }
+            None => import.extra.push(title.clone()),
+        }
+    }
+
+    let mut cells: HashMap<u32, Vec<String>> = HashMap::new();
+    let mut row_order = vec![];
+    for (number, row) in rows {
+        if row.len() != header.len() {
+            return Err(Error::parsing(format!(
+                "line {}: {} fields, but the header has {}",
+                number,
+                row.len(),
+                header.len()
+            )));
+        }
+        let identifier = parse_identifier(&row[0]).map_err(|_| {
+            Error::parsing(format!(
+                "line {}: \"{}\" is not an identifier",
+                number, row[0]
+            ))
+        })?;
+        if cells.insert(identifier, row).is_some() {
+            return Err(Error::parsing(format!(
+                "line {}: identifier {:08x} appears more than once",
+                number, identifier
+            )));
+        }
+        row_order.push(identifier);
+    }
+
+    let mut missing = HashSet::new();
+    for (language, lines) in languages {
+        let Some(&column) = columns.get(language) else {
+            import.languages.push((*language, lines.to_vec()));
+            continue;
+        };
+        let mut updated: Vec<TextLine> = lines
+            .iter()
+            .map(|line| match cells.get(&line.identifier) {
+                Some(row) => TextLine {
+                    identifier: line.identifier,
+                    text: row[column].clone(),
+                },
+                None => {
+                    missing.insert(line.identifier);
+                    line.clone()
+                }
+            })
+            .collect();
+
+        let known: HashSet<u32> = lines.iter().map(|line| line.identifier).collect();
+        for identifier in row_order.iter().filter(|id| !known.contains(id)) {
+            let text = &cells[identifier][column];
+            if !text.is_empty() {
+                import.added.push((*language, *identifier));
+                updated.push(TextLine {
+                    identifier: *identifier,
+                    text: text.clone(),
+                });
+            }
+        }
+        import.languages.push((*language, updated));
+    }
+
+    import.missing = missing.into_iter().collect();
+    import.missing.sort();
+    Ok(import)
+}
+
+/// Returns the line identifiers which only some of the languages have.
+pub fn partial_lines(languages: &[(u32, &[TextLine])]) -> Vec<u32> {
+    let identifiers: Vec<HashSet<u32>> = languages
+        .iter()
+        .map(|(_, lines)| lines.iter().map(|line| line.identifier).collect())
+        .collect();
+    line_identifiers(languages)
+        .into_iter()
+        .filter(|id| !identifiers.iter().all(|ids| ids.contains(id)))
+        .collect()
+}
+
+/// Returns the identifiers of all lines, in the order in which they first
+/// appear in the languages.
+fn line_identifiers(languages: &[(u32, &[TextLine])]) -> Vec<u32> {
+    let mut seen = HashSet::new();
+    languages
+        .iter()
+        .flat_map(|(_, lines)| lines.iter())
+        .map(|line| line.identifier)
+        .filter(|identifier| seen.insert(*identifier))
+        .collect()
+}
+
+fn write_row(sheet: &mut String, fields: &[String], delimiter: char) {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([delimiter, '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    sheet.push_str(&fields.join(&delimiter.to_string()));
+    sheet.push('\n');
+}
+
+/// Splits a sheet into rows of fields, together with the line on which each
+/// row starts. Empty lines are skipped.
+fn parse_rows(sheet: &str, delimiter: char) -> Result<Vec<(usize, Vec<String>)>, Error> {
+    // Spreadsheet programs like to start UTF-8 files with a byte order mark
+    let sheet = sheet.strip_prefix('\u{feff}').unwrap_or(sheet);
+
+    let mut rows = vec![];
+    let mut row: Vec<String> = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut row_line = 1;
+    let mut chars = sheet.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            '\n' if quoted => {
+                line += 1;
+                field.push(c);
+            }
+            _ if quoted => field.push(c),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                if row.len() > 1 || !row[0].is_empty() {
+                    rows.push((row_line, std::mem::take(&mut row)));
+                }
+                row.clear();
+                line += 1;
+                row_line = line;
+            }
+            _ if c == delimiter => row.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(Error::parsing(format!(
+            "line {}: quoted field is not closed",
+            row_line
+        )));
+    }
+    if !row.is_empty() || !field.is_empty() {
+        row.push(field);
+        rows.push((row_line, row));
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(texts: &[(u32, &str)]) -> Vec<TextLine> {
+        texts
+            .iter()
+            .map(|(identifier, text)| TextLine {
+                identifier: *identifier,
+                text: text.to_string(),
+            })
+            .collect()
+    }
+
+    fn english() -> Vec<TextLine> {
+        lines(&[
+            (1, "Hello, \"George\""),
+            (2, ""),
+            (3, "First line\nSecond line\r\nThird line\r"),
+            (4, "Tab\there"),
+            (5, "Only in English"),
+        ])
+    }
+
+    fn german() -> Vec<TextLine> {
+        lines(&[
+            (1, "Hallo, \"George\""),
+            (2, ""),
+            (3, "Erste Zeile\nZweite Zeile\r\nDritte Zeile\r"),
+            (4, "Tab\thier"),
+        ])
+    }
+
+    #[test]
+    fn texts_survive_export_and_import() {
+        for delimiter in [',', '\t'] {
+            let languages: [(u32, &[TextLine]); 2] = [(0x0a, &english()), (0x0b, &german())];
+            let sheet = export_sheet(&languages, delimiter);
+            let import = import_sheet(&languages, &sheet, delimiter).unwrap();
+            assert_eq!(import.languages, vec![(0x0a, english()), (0x0b, german())]);
+            assert!(import.missing.is_empty());
+            assert!(import.added.is_empty());
+            assert!(import.extra.is_empty());
+        }
+    }
+
+    #[test]
+    fn edited_cells_change_and_add_lines() {
+        let languages: [(u32, &[TextLine]); 2] = [(0x0a, &english()), (0x0b, &german())];
+        let sheet = export_sheet(&languages, ',')
+            .replace("\"Hallo, \"\"George\"\"\"", "\"Guten Tag, \"\"George\"\"\"")
+            .replace("Only in English,", "Only in English,\"Nur, auf Deutsch\"")
+            // Spreadsheet programs end rows with CRLF
+            .replace("0000000b\n", "0000000b\r\n");
+        // and start UTF-8 files with a byte order mark
+        let sheet = format!("\u{feff}{}", sheet);
+
+        let import = import_sheet(&languages, &sheet, ',').unwrap();
+        let (_, updated) = &import.languages[1];
+        assert_eq!(updated[0].text, "Guten Tag, \"George\"");
+        assert_eq!(updated[2], german()[2]);
+        assert_eq!(updated[4].text, "Nur, auf Deutsch");
+        assert_eq!(import.added, vec![(0x0b, 5)]);
+    }
+
+    #[test]
+    fn unclosed_quotes_are_rejected() {
+        let sheet = "identifier,0000000a\n00000001,\"Hello\n";
+        assert!(import_sheet(&[(0x0a, &english())], sheet, ',').is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::{HashMap, HashSet};
+
+use xml::reader::{EventReader, XmlEvent as ReadEvent};
+use xml::writer::{EmitterConfig, XmlEvent as WriteEvent};
+
+use super::{format_identifier, parse_identifier, TextImport, Translation};
+use crate::codecs::TextLine;
+use crate::error::Error;
+
+const XLIFF_NAMESPACE: &str = "urn:oasis:names:tc:xliff:document:2.0";
+
+/// File id of templates, which do not belong to a translated language entry.
+const TEMPLATE_ID: &str = "template";
+
+/// Writes an XLIFF 2.0 document for translating the lines of `reference`.
+///
+/// Every line becomes a unit with its identifier as id and its text as
+/// source. With a `translation`, given as identifier and lines of the
+/// language entry, its texts become the targets and its identifier becomes
+/// the id of the file element. The languages are given as BCP 47 tags like
+/// `en` or `de`, the target language is left out of templates. A translation
+/// needs a target language.
+///
+/// ```no_run
+/// use vt7packer::codecs::TextLine;
+/// use vt7packer::text;
+///
+/// let english: Vec<TextLine> = serde_json::from_slice(&std::fs::read("0000000a.sword_text.json")?)?;
+/// let german: Vec<TextLine> = serde_json::from_slice(&std::fs::read("0000000b.sword_text.json")?)?;
+/// let xliff = text::export_xliff(&english, "en", Some((0x0000000b, &german)), Some("de"))?;
+/// std::fs::write("0000000b.xlf", xliff)?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn export_xliff(
+    reference: &[TextLine],
+    source_language: &str,
+    translation: Option<(u32, &[TextLine])>,
+    target_language: Option<&str>,
+) -> Result<String, Error> {
+    if translation.is_some() && target_language.is_none() {
+        return Err(Error::parsing(
+            "XLIFF documents with translations need a target language",
+        ));
+    }
+    let translated: HashMap<u32, &str> = translation
+        .map(|(_, lines)| {
+            lines
+                .iter()
+                .map(|line| (line.identifier, line.text.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let file_id = match translation {
+        Some((identifier, _)) => format_identifier(identifier),
+        None => TEMPLATE_ID.to_string(),
+    };
+
+    // Texts are escaped by `escape`, as the writer would keep carriage returns,
+    // which XML parsers turn into line feeds. Identifiers need no escaping.
+    let mut config = EmitterConfig::new().perform_indent(true);
+    config.perform_escaping = false;
+    let mut output = vec![];
+    let mut writer = config.create_writer(&mut output);
+    let mut write = |event: WriteEvent| {
+        writer
+            .write(event)
+            .map_err(|e| Error::parsing(format!("Cannot write XLIFF: {}", e)))
+    };
+
+    let source_language = escape_attribute(source_language);
+    let target_language = target_language.map(escape_attribute);
+    let mut root = WriteEvent::start_element("xliff")
+        .default_ns(XLIFF_NAMESPACE)
+        .attr("version", "2.0")
+        .attr("srcLang", &source_language);
+    if let Some(target_language) = &target_language {
+        root = root.attr("trgLang", target_language);
+    }
+    write(root.into())?;
+    write(
+        WriteEvent::start_element("file")
+            .attr("id", &file_id)
+            .into(),
+    )?;
+    for line in reference {
+        let unit_id = format_identifier(line.identifier);
+        let target = translated
+            .get(&line.identifier)
+            .copied()
+            .unwrap_or_default();
+        let state = match target.is_empty() && !line.text.is_empty() {
+            true => "initial",
+            false => "translated",
+        };
+
+        write(
+            WriteEvent::start_element("unit")
+                .attr("id", &unit_id)
+                .into(),
+        )?;
+        write(
+            WriteEvent::start_element("segment")
+                .attr("state", state)
+                .into(),
+        )?;
+        write(WriteEvent::start_element("source").into())?;
+        write(WriteEvent::characters(&escape(&line.text)))?;
+        write(WriteEvent::end_element().into())?;
+        if state == "translated" {
+            write(WriteEvent::start_element("target").into())?;
+            write(WriteEvent::characters(&escape(target)))?;
+            write(WriteEvent::end_element().into())?;
+        }
+        write(WriteEvent::end_element().into())?;
+        write(WriteEvent::end_element().into())?;
+    }
+    write(WriteEvent::end_element().into())?;
+    write(WriteEvent::end_element().into())?;
+
+    output.push(b'\n');
+    Ok(String::from_utf8(output).expect("XML writer produces UTF-8"))
+}
+
+/// Escapes text for the content of an element.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\r', "&#xD;")
+}
+
+/// Escapes text for an attribute value.
+fn escape_attribute(text: &str) -> String {
+    escape(text).replace('"', "&quot;").replace('\n', "&#xA;")
+}
+
+/// Reads a translated XLIFF 2.0 document written by [`export_xliff`] and
+/// builds the lines of the translated language.
+///
+/// Every line of the `reference` needs a unit, otherwise the document was
+/// made for a different language file and an error is returned. Units without
+/// target, in the state `initial` or with a source that differs from the
+/// reference keep the reference text. Inline markup is not supported.
+pub fn import_xliff(reference: &[TextLine], xliff: &str) -> Result<TextImport, Error> {
+    let units = parse_xliff(xliff)?;
+    let mut import = TextImport::default();
+
+    let known_identifiers: HashSet<u32> = reference.iter().map(|line| line.identifier).collect();
+    let mut translations: HashMap<u32, Translation> = HashMap::new();
+    for unit in units {
+        if let Some(file_id) = &unit.file_id {
+            if file_id != TEMPLATE_ID {
+                import.identifier = Some(parse_identifier(file_id).map_err(|_| {
+                    Error::parsing(format!("file id \"{}\" is not an identifier", file_id))
+                })?);
+            }
+        }
+
+        let identifier = parse_identifier(&unit.id)
+            .ok()
+            .filter(|i| known_identifiers.contains(i));
+        let Some(identifier) = identifier else {
+            import.extra.push(unit.id);
+            continue;
+        };
+        let translation = Translation {
+            source: unit.source,
+            target: unit.target.unwrap_or_default(),
+            fuzzy: unit.state.as_deref() == Some("initial"),
+        };
+        if translations.insert(identifier, translation).is_some() {
+            return Err(Error::parsing(format!(
+                "unit \"{}\" appears more than once",
+                unit.id
+            )));
+        }
+    }
+
+    let absent: Vec<String> = reference
+        .iter()
+        .filter(|line| !translations.contains_key(&line.identifier))
+        .map(|line| format_identifier(line.identifier))
+        .collect();
+    if !absent.is_empty() {
+        return Err(Error::parsing(format!(
+            "{} lines of the reference have no unit: {}",
+            absent.len(),
+            absent.join(", ")
+        )));
+    }
+
+    import.merge(reference, &translations);
+    Ok(import)
+}
+
+/// A unit of an XLIFF document.
+#[derive(Debug, Default)]
+struct Unit {
+    /// Id of the enclosing file element, only set for the first unit of a file
+    file_id: Option<String>,
+    id: String,
+    source: String,
+    target: Option<String>,
+    state: Option<String>,
+}
+
+fn parse_xliff(xliff: &str) -> Result<Vec<Unit>, Error> {
+    let mut units = vec![];
+    let mut unit: Option<Unit> = None;
+    let mut file_id: Option<String> = None;
+    // Element whose text is collected, "source" or "target"
+    let mut text_element: Option<String> = None;
+    let mut version_checked = false;
+
+    for event in EventReader::new(xliff.as_bytes()) {
+        let event = event.map_err(|e| Error::parsing(format!("Invalid XLIFF: {}", e)))?;
+        match event {
+            ReadEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let attribute = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|a| a.name.local_name == key)
+                        .map(|a| a.value.clone())
+                };
+                if text_element.is_some() {
+                    return Err(Error::parsing(format!(
+                        "Inline element <{}> is not supported",
+                        name.local_name
+                    )));
+                }
+                match name.local_name.as_str() {
+                    "xliff" => {
+                        if attribute("version").as_deref() != Some("2.0") {
+                            return Err(Error::parsing(format!(
+                                "Unsupported XLIFF version {:?}, expected 2.0",
+                                attribute("version")
+                            )));
+                        }
+                        version_checked = true;
+                    }
+                    "file" => file_id = attribute("id"),
+                    "unit" => {
+                        let id =
+                            attribute("id").ok_or_else(|| Error::parsing("unit without id"))?;
+                        unit = Some(Unit {
+                            file_id: file_id.take(),
+                            id,
+                            ..Default::default()
+                        });
+                    }
+                    "segment" => {
+                        if let Some(unit) = &mut unit {
+                            unit.state = attribute("state");
+                        }
+                    }
+                    element @ ("source" | "target") => {
+                        if let Some(unit) = &mut unit {
+                            if element == "target" {
+                                unit.target = Some(String::new());
+                            }
+                            text_element = Some(element.to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            ReadEvent::Characters(text) | ReadEvent::CData(text) | ReadEvent::Whitespace(text) => {
+                if let (Some(unit), Some(element)) = (&mut unit, &text_element) {
+                    match element.as_str() {
+                        "source" => unit.source.push_str(&text),
+                        _ => unit.target.get_or_insert_with(String::new).push_str(&text),
+                    }
+                }
+            }
+            ReadEvent::EndElement { name } => match name.local_name.as_str() {
+                "source" | "target" => text_element = None,
+                "unit" => units.extend(unit.take()),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    if !version_checked {
+        return Err(Error::parsing("Not an XLIFF document"));
+    }
+    Ok(units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(texts: &[(u32, &str)]) -> Vec<TextLine> {
+        texts
+            .iter()
+            .map(|(identifier, text)| TextLine {
+                identifier: *identifier,
+                text: text.to_string(),
+            })
+            .collect()
+    }
+
+    fn reference() -> Vec<TextLine> {
+        lines(&[
+            (1, "He said \"Hello\" & <waved>"),
+            (2, ""),
+            (3, "First line\nSecond line\n"),
+            (4, "  Tab\there, return\r\nand a lone return\r"),
+        ])
+    }
+
+    #[test]
+    fn translations_survive_export_and_import() {
+        let translation = lines(&[
+            (1, "Er sagte \"Hallo\" & <winkte>"),
+            (2, ""),
+            (3, "Erste Zeile\nZweite Zeile\n"),
+            (4, "  Tab\thier, Wagenrücklauf\r\nund ein einzelner\r"),
+        ]);
+        let xliff =
+            export_xliff(&reference(), "en", Some((0x0b, &translation)), Some("de")).unwrap();
+        assert!(xliff.contains("trgLang=\"de\""));
+        assert!(xliff.contains("Wagenrücklauf&#xD;\nund"));
+        assert!(!xliff.contains('\r'));
+
+        let import = import_xliff(&reference(), &xliff).unwrap();
+        assert_eq!(import.identifier, Some(0x0b));
+        assert_eq!(import.lines, translation);
+        assert!(import.missing.is_empty());
+        assert!(import.fuzzy.is_empty());
+        assert!(import.extra.is_empty());
+    }
+
+    #[test]
+    fn templates_keep_the_reference_text() {
+        let xliff = export_xliff(&reference(), "en", None, None).unwrap();
+        assert!(!xliff.contains("trgLang"));
+
+        let import = import_xliff(&reference(), &xliff).unwrap();
+        assert_eq!(import.identifier, None);
+        assert_eq!(import.lines, reference());
+        assert_eq!(import.missing, vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn translations_need_a_target_language() {
+        let translation = lines(&[(1, "Hallo")]);
+        assert!(export_xliff(&reference(), "en", Some((0x0b, &translation)), None).is_err());
+    }
+
+    #[test]
+    fn documents_missing_a_line_are_rejected() {
+        let xliff = export_xliff(&reference()[..2], "en", None, None).unwrap();
+        assert!(import_xliff(&reference(), &xliff).is_err());
+    }
+}